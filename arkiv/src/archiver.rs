@@ -1,8 +1,13 @@
-use crate::config::{Config, CustomRegex};
+use crate::{
+    config::{BoardConfig, Config, MediaSkipReason, PostFilterAction, ThreadAction},
+    filter, stats,
+    thumbnailer::Thumbnailer,
+};
+use arkiv_storage::StorageError;
 use bytes::Bytes;
-use fourchan::{BoardsResponse, Post, PostAttachment, ThreadResponse};
-use futures::Future;
-use scraper::{Html, Node};
+use chrono::Utc;
+use fourchan::{BoardsResponse, Comment, Post, PostAttachment, ThreadResponse};
+use futures::{Future, TryStreamExt};
 use std::{
     sync::Arc,
     time::{Duration, SystemTime},
@@ -10,6 +15,36 @@ use std::{
 use tokio::sync::Semaphore;
 use tracing::{debug, info, trace, trace_span, warn};
 
+/// How many posts are saved at the same time
+const MAX_TASKS: u32 = 4;
+
+/// Selects the posts [`Archiver::backfill_media`] downloads missing media for
+#[derive(Debug, Clone)]
+pub struct BackfillFilter {
+    pub board: Option<String>,
+
+    /// Only include posts created at or after this UNIX timestamp
+    pub since: Option<i64>,
+
+    /// Only include posts created before this UNIX timestamp
+    pub until: Option<i64>,
+
+    /// File extensions to include. Empty includes all
+    pub extensions: Vec<String>,
+
+    /// Also download media the board's media policy would skip
+    pub ignore_policy: bool,
+
+    /// Maximum number of files to download
+    pub limit: Option<usize>,
+
+    /// Only list the missing files
+    pub dry_run: bool,
+
+    /// Maximum number of downloads per second
+    pub rate: f64,
+}
+
 #[derive(Clone)]
 pub struct Archiver<S: arkiv_storage::Storage> {
     client: fourchan::Client,
@@ -17,6 +52,7 @@ pub struct Archiver<S: arkiv_storage::Storage> {
     storage: S,
     config: Config,
     semaphore: Arc<Semaphore>,
+    thumbnailer: Option<Thumbnailer>,
 }
 
 impl<S> Archiver<S>
@@ -25,16 +61,23 @@ where
 {
     pub fn new(pool: sqlx::SqlitePool, storage: S, config: Config) -> Self {
         let client = fourchan::Client::new();
+        let thumbnailer = config.thumbnailer.as_ref().map(Thumbnailer::new);
         Archiver {
             client,
             pool,
             storage,
             config,
-            semaphore: Arc::new(Semaphore::new(4)),
+            semaphore: Arc::new(Semaphore::new(MAX_TASKS as usize)),
+            thumbnailer,
         }
     }
 
-    #[allow(clippy::missing_errors_doc, clippy::too_many_lines)]
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    #[allow(clippy::missing_errors_doc)]
     pub async fn run(self) -> anyhow::Result<()> {
         debug!("archiver running");
 
@@ -59,104 +102,326 @@ where
                     page.page,
                     &board_name
                 );
-                'page_loop: for thread_entry in page.threads {
-                    debug!("archiving thread no {}", thread_entry.no);
-
-                    let time_started = SystemTime::now();
-
-                    match self
-                        .client
-                        .get_thread(&board.board, thread_entry.no)
-                        .await?
-                    {
-                        ThreadResponse::Thread(thread) => {
-                            if !board_cfg.filters.is_empty() {
-                                if let Some(post) = thread.posts.get(0) {
-                                    let _span_guard = trace_span!("filter").entered();
-                                    let mut filter_match = false;
-                                    for CustomRegex(filter) in &board_cfg.filters {
-                                        if let Some(sub) = &post.sub {
-                                            if filter.is_match(sub) {
-                                                filter_match = true;
-                                                break;
-                                            }
-                                        }
-                                        if board_cfg.filter_comment {
-                                            if let Some(com) = &post.com {
-                                                let com_text = Html::parse_document(com)
-                                                    .tree
-                                                    .nodes()
-                                                    .fold(String::new(), |mut s, n| {
-                                                        if let Node::Text(t) = n.value() {
-                                                            s.push_str(t);
-                                                        }
-                                                        s
-                                                    });
-                                                if filter.is_match(&com_text) {
-                                                    filter_match = true;
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    }
-                                    if filter_match ^ board_cfg.reverse_filter {
-                                        trace!(?post, "skipping thread");
-                                        continue 'page_loop;
-                                    }
-                                    trace!(?post, "saving thread");
-                                }
-                            }
+                for thread_entry in page.threads {
+                    self.archive_thread(&board.board, &board_cfg, thread_entry.no)
+                        .await?;
+                }
+            }
+            if let Err(err) = stats::refresh(&self.pool, &board_name).await {
+                warn!("failed to refresh the stats of /{}/: {}", &board_name, err);
+            }
 
-                            for post in thread.posts {
-                                let permit = Arc::clone(&self.semaphore).acquire_owned().await?;
-                                let archiver = self.clone();
-                                let board = board.clone();
-                                tokio::spawn(async move {
-                                    debug!("archiving post no {}", post.no);
-                                    archiver.save_post(&post, &board.board).await?;
-
-                                    if let Some(attachment) = post.attachment() {
-                                        if board_cfg.full_media {
-                                            archiver
-                                                .save_attachment(&board.board, &attachment)
-                                                .await?;
-                                        }
-                                        archiver.save_thumbnail(&board.board, &attachment).await?;
-                                    }
-                                    debug!("archived post no {}", &post.no);
-
-                                    drop(permit);
-                                    anyhow::Ok(())
-                                });
-                            }
+            debug!("waiting 10 minutes until next archival");
+            tokio::time::sleep(Duration::from_secs(60 * 10)).await;
+        }
+
+        Ok(())
+    }
 
-                            let elapsed = time_started.elapsed()?.as_secs_f64();
-                            info!(
-                                "archived thread no {} on /{}/ in {:.2}s",
-                                thread_entry.no, &board.board, elapsed
-                            );
+    /// Archives a thread once, as decided by the board's rules. Returns what was decided,
+    /// or `None` if the thread couldn't be fetched.
+    ///
+    /// The posts are saved in the background, see [`Archiver::wait`].
+    #[allow(clippy::too_many_lines)]
+    pub async fn archive_thread(
+        &self,
+        board: &str,
+        board_cfg: &BoardConfig,
+        no: i64,
+    ) -> anyhow::Result<Option<ThreadAction>> {
+        debug!("archiving thread no {}", no);
+
+        let time_started = SystemTime::now();
+
+        match self.client.get_thread(board, no).await? {
+            ThreadResponse::Thread(thread) => {
+                let thread_action = match thread.posts.get(0) {
+                    Some(op) => self.track_thread(board, board_cfg, op).await?,
+                    None => ThreadAction::Archive,
+                };
+                if thread_action == ThreadAction::Skip {
+                    return Ok(Some(thread_action));
+                }
+
+                for mut post in thread.posts {
+                    if self.is_purged(board, &post).await? {
+                        trace!(no = post.no, "skipping purged post");
+                        continue;
+                    }
+                    let action = filter::post_action(board_cfg, &post);
+                    match action {
+                        Some(PostFilterAction::Drop) => {
+                            trace!(no = post.no, "dropping post");
+                            continue;
                         }
-                        ThreadResponse::NotModified => {
-                            debug!(
-                                "thread no {} on /{}/ was not modified",
-                                thread_entry.no, &board.board
-                            );
+                        Some(PostFilterAction::Redact) => {
+                            trace!(no = post.no, "redacting post");
+                            filter::redact(&mut post);
                         }
-                        ThreadResponse::NotFound => {
-                            warn!(
-                                "thread no {} on /{}/ could not be found",
-                                thread_entry.no, &board.board
-                            );
+                        Some(PostFilterAction::RemoveMedia) | None => {}
+                    }
+
+                    let permit = Arc::clone(&self.semaphore).acquire_owned().await?;
+                    let archiver = self.clone();
+                    let board = board.to_string();
+                    let board_cfg = board_cfg.clone();
+                    tokio::spawn(async move {
+                        debug!("archiving post no {}", post.no);
+                        let blocked = match &post.md5 {
+                            Some(md5) => archiver.is_blocked(&board, md5).await?,
+                            None => false,
+                        };
+                        if blocked {
+                            trace!(no = post.no, "media is on the blocklist");
+                            post.media_removed = 1;
+                        }
+                        archiver.save_post(&post, &board).await?;
+
+                        let attachment = match action {
+                            Some(PostFilterAction::RemoveMedia) => None,
+                            _ if blocked => None,
+                            _ => post.attachment(),
+                        };
+                        if let Some(attachment) = attachment {
+                            let skip_reason = if thread_action == ThreadAction::TextOnly {
+                                Some(MediaSkipReason::TextOnly)
+                            } else {
+                                board_cfg.media_skip_reason(&post, &attachment)
+                            };
+                            match skip_reason {
+                                None => {
+                                    archiver.save_attachment(&board, &attachment).await?;
+                                }
+                                Some(reason) => {
+                                    archiver
+                                        .record_skipped_media(&board, &post, &attachment, reason)
+                                        .await?;
+                                }
+                            }
+                            if thread_action != ThreadAction::TextOnly {
+                                archiver.save_thumbnail(&board, &attachment).await?;
+                            }
                         }
+                        debug!("archived post no {}", &post.no);
+
+                        drop(permit);
+                        anyhow::Ok(())
+                    });
+                }
+
+                let elapsed = time_started.elapsed()?.as_secs_f64();
+                info!(
+                    "archived thread no {} on /{}/ in {:.2}s",
+                    no, board, elapsed
+                );
+                Ok(Some(thread_action))
+            }
+            ThreadResponse::NotModified => {
+                debug!("thread no {} on /{}/ was not modified", no, board);
+                Ok(None)
+            }
+            ThreadResponse::NotFound => {
+                warn!("thread no {} on /{}/ could not be found", no, board);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Waits until the posts saved in the background are done
+    pub async fn wait(&self) -> anyhow::Result<()> {
+        let _permits = self.semaphore.acquire_many(MAX_TASKS).await?;
+        Ok(())
+    }
+
+    /// Decides what happens to a thread and stores the decision, see
+    /// [`filter::tracked_action`]
+    async fn track_thread(
+        &self,
+        board: &str,
+        board_cfg: &BoardConfig,
+        op: &Post,
+    ) -> anyhow::Result<ThreadAction> {
+        let tracked = query!(
+            "SELECT action, rule, pinned FROM tracked_threads WHERE board = ? AND no = ?",
+            board,
+            op.no
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let (action, rule) = {
+            let _span_guard = trace_span!("filter").entered();
+            let rules = filter::thread_action(board_cfg, op);
+            let pinned = tracked
+                .as_ref()
+                .map_or(false, |tracked| tracked.pinned != 0);
+            // archiving because of a pin that was removed doesn't keep the thread tracked
+            let previous = match &tracked {
+                Some(tracked) if tracked.rule.as_deref() != Some(filter::PINNED) => {
+                    Some(tracked.action.parse()?)
+                }
+                _ => None,
+            };
+            filter::tracked_action(rules, previous, pinned)
+        };
+        trace!(no = op.no, ?action, ?rule, "evaluated thread");
+
+        let action_str = action.as_str();
+        let now = Utc::now().timestamp();
+        query!(
+            r#"
+            INSERT INTO tracked_threads (board, no, action, rule, pinned, first_seen, updated_at)
+            VALUES (?, ?, ?, ?, 0, ?, ?)
+            ON CONFLICT(board, no) DO UPDATE
+            SET action = excluded.action, rule = excluded.rule, updated_at = excluded.updated_at;
+            "#,
+            board,
+            op.no,
+            action_str,
+            rule,
+            now,
+            now,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(action)
+    }
+
+    /// Downloads the full media of archived posts whose files are missing from the storage,
+    /// e.g. because `full_media` was disabled or the download failed.
+    pub async fn backfill_media(&self, filter: &BackfillFilter) -> anyhow::Result<()> {
+        let mut missing = Vec::new();
+        {
+            let mut posts = query_as!(
+                Post,
+                r#"
+                SELECT * FROM posts
+                WHERE tim IS NOT NULL AND filedeleted = 0 AND media_removed = 0
+                    AND (?1 IS NULL OR board = ?1)
+                    AND (?2 IS NULL OR time >= ?2)
+                    AND (?3 IS NULL OR time < ?3)
+                ORDER BY time DESC
+                "#,
+                filter.board,
+                filter.since,
+                filter.until,
+            )
+            .fetch(&self.pool);
+
+            while let Some(post) = posts.try_next().await? {
+                let attachment = match post.attachment() {
+                    Some(attachment) => attachment,
+                    None => continue,
+                };
+                let ext = attachment.ext.trim_start_matches('.');
+                if !filter.extensions.is_empty()
+                    && !filter
+                        .extensions
+                        .iter()
+                        .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+                {
+                    continue;
+                }
+                if !filter.ignore_policy {
+                    let skip_reason = self
+                        .config
+                        .boards
+                        .get(&post.board)
+                        .and_then(|board_cfg| board_cfg.media_skip_reason(&post, &attachment));
+                    if let Some(reason) = skip_reason {
+                        trace!(no = post.no, reason = reason.as_str(), "media skipped by policy");
+                        continue;
                     }
                 }
+
+                if self.is_blocked(&post.board, &attachment.md5).await? {
+                    continue;
+                }
+
+                let key = format!("{}{}", &attachment.tim, &attachment.ext);
+                if self.storage.exists(&key, Some(&post.board)).await? {
+                    continue;
+                }
+                missing.push((post.board, attachment));
+
+                if filter.limit.map_or(false, |limit| missing.len() >= limit) {
+                    break;
+                }
             }
-            debug!("waiting 10 minutes until next archival");
-            tokio::time::sleep(Duration::from_secs(60 * 10)).await;
+        }
+        info!("found {} attachments with missing media", missing.len());
+
+        if filter.dry_run {
+            for (board, attachment) in &missing {
+                info!("/{}/{}{}", board, attachment.tim, attachment.ext);
+            }
+            return Ok(());
         }
 
+        let (mut saved, mut gone, mut failed) = (0, 0, 0);
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / filter.rate));
+        for (board, attachment) in &missing {
+            interval.tick().await;
+            match self.save_attachment(board, attachment).await {
+                Ok(()) => {
+                    debug!("backfilled /{}/{}{}", board, attachment.tim, attachment.ext);
+                    saved += 1;
+                }
+                Err(err) if is_not_found(&err) => {
+                    debug!(
+                        "/{}/{}{} is no longer available",
+                        board, attachment.tim, attachment.ext
+                    );
+                    gone += 1;
+                }
+                Err(err) => {
+                    warn!(
+                        "failed to backfill /{}/{}{}: {}",
+                        board, attachment.tim, attachment.ext, err
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        info!(saved, gone, failed, "media backfill done");
+
         Ok(())
     }
+    /// Whether an admin purged the post or its thread from the archive
+    async fn is_purged(&self, board: &str, post: &Post) -> sqlx::Result<bool> {
+        let purged = query_scalar!(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM purged_posts WHERE board = ? AND no IN (?, ?))
+                as "purged!: bool"
+            "#,
+            board,
+            post.no,
+            post.resto
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(purged)
+    }
+    /// Whether the file is on the board's `md5_blocklist` or an admin blocked it, in which
+    /// case it must never be downloaded again
+    async fn is_blocked(&self, board: &str, md5: &str) -> sqlx::Result<bool> {
+        let configured = self.config.boards.get(board).map_or(false, |board_cfg| {
+            board_cfg.md5_blocklist.iter().any(|b| b == md5)
+        });
+        if configured {
+            return Ok(true);
+        }
+        let blocked = query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM md5_blocklist WHERE md5 = ?) as "blocked!: bool""#,
+            md5
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(blocked)
+    }
     async fn save_file<B>(&self, key: &str, subdir: Option<&str>, body: B) -> anyhow::Result<()>
     where
         B: Future<Output = anyhow::Result<Bytes>>,
@@ -180,30 +445,128 @@ where
             .client
             .get_attachment_body(board, attachment.tim, &attachment.ext);
         self.save_file(&key, Some(board), body_fut).await?;
+
+        query!(
+            "DELETE FROM skipped_media WHERE board = ? AND tim = ?",
+            board,
+            attachment.tim
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+    /// Remembers that the full media of a post wasn't saved, so it can be backfilled
+    /// if the media policy changes.
+    async fn record_skipped_media(
+        &self,
+        board: &str,
+        post: &Post,
+        attachment: &PostAttachment,
+        reason: MediaSkipReason,
+    ) -> sqlx::Result<()> {
+        trace!(no = post.no, reason = reason.as_str(), "skipping media");
+        let reason = reason.as_str();
+        let skipped_at = Utc::now().timestamp();
+        query!(
+            r#"
+            INSERT INTO skipped_media (board, no, tim, ext, reason, skipped_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(board, tim) DO UPDATE SET reason = excluded.reason;
+            "#,
+            board,
+            post.no,
+            attachment.tim,
+            attachment.ext,
+            reason,
+            skipped_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
     async fn save_thumbnail(&self, board: &str, attachment: &PostAttachment) -> anyhow::Result<()> {
         let key = format!("{}s.jpg", &attachment.tim);
         let body_fut = self.client.get_thumbnail_body(board, attachment.tim);
-        self.save_file(&key, Some(board), body_fut).await?;
+        match (self.save_file(&key, Some(board), body_fut).await, &self.thumbnailer) {
+            (Ok(()), _) => {}
+            (Err(err), Some(thumbnailer)) => {
+                debug!("failed to fetch thumbnail {}: {}", &key, err);
+                self.generate_thumbnail(thumbnailer, board, attachment)
+                    .await?;
+            }
+            (Err(err), None) => return Err(err),
+        }
+
+        if let Err(err) = hash_thumbnail(&self.pool, &self.storage, board, attachment.tim).await {
+            warn!("failed to hash thumbnail {}: {}", &key, err);
+        }
+
+        Ok(())
+    }
+
+    /// Generates a thumbnail from the saved full media of an attachment
+    async fn generate_thumbnail(
+        &self,
+        thumbnailer: &Thumbnailer,
+        board: &str,
+        attachment: &PostAttachment,
+    ) -> anyhow::Result<()> {
+        let media_key = format!("{}{}", &attachment.tim, &attachment.ext);
+        if !thumbnailer.supports(&attachment.ext)
+            || !self.storage.exists(&media_key, Some(board)).await?
+        {
+            debug!("can't generate thumbnail for {}", &media_key);
+            return Ok(());
+        }
+
+        let body = self.storage.get(&media_key, Some(board)).await?;
+        let thumbnail = thumbnailer
+            .generate(body.into(), &attachment.ext)
+            .await?;
+
+        let key = format!("{}s.jpg", &attachment.tim);
+        self.storage.save(&key, Some(board), &thumbnail.body).await?;
+
+        query!(
+            r#"
+            INSERT INTO generated_thumbnails (board, tim, tn_w, tn_h)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(board, tim) DO UPDATE SET tn_w = excluded.tn_w, tn_h = excluded.tn_h;
+            "#,
+            board,
+            attachment.tim,
+            thumbnail.width,
+            thumbnail.height,
+        )
+        .execute(&self.pool)
+        .await?;
+        debug!("generated thumbnail {}", &key);
+
         Ok(())
     }
+    /// Stores a post together with the quote links in its comment
     async fn save_post(&self, post: &Post, board: &str) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
         query!(
             r#"
             INSERT INTO posts (no, resto, sticky, closed, now, time, name, trip,
                 id, capcode, country, country_name, board_flag, flag_name, sub, com,
                 tim, filename, ext, fsize, md5, w, h, tn_w,
                 tn_h, filedeleted, spoiler, custom_spoiler, replies, images, bumplimit, imagelimit,
-                tag, semantic_url, since4pass, unique_ips, m_img, archived, archived_on, board)
+                tag, semantic_url, since4pass, unique_ips, m_img, archived, archived_on, board,
+                media_removed)
             VALUES
             (?, ?, ?, ?, ?, ?, ?, ?,
                 ?, ?, ?, ?, ?, ?, ?, ?,
                 ?, ?, ?, ?, ?, ?, ?, ?,
                 ?, ?, ?, ?, ?, ?, ?, ?,
-                ?, ?, ?, ?, ?, ?, ?, ?)
+                ?, ?, ?, ?, ?, ?, ?, ?,
+                ?)
             ON CONFLICT(no) DO UPDATE
-            SET filedeleted = ?, replies = ?, images = ?, bumplimit = ?,
+            SET filedeleted = ?, media_removed = max(media_removed, ?), replies = ?, images = ?, bumplimit = ?,
                 imagelimit = ?, unique_ips = ?, archived = ?, archived_on = ?;
             "#,
             post.no,
@@ -246,7 +609,9 @@ where
             post.archived,
             post.archived_on,
             board,
+            post.media_removed,
             post.filedeleted,
+            post.media_removed,
             post.replies,
             post.images,
             post.bumplimit,
@@ -255,9 +620,93 @@ where
             post.archived,
             post.archived_on,
         )
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await?;
 
-        Ok(())
+        if let Some(com) = &post.com {
+            let thread = if post.resto == 0 { post.no } else { post.resto };
+            for quote in Comment::parse(com).quotes() {
+                let target_board = quote.board.as_deref().unwrap_or(board);
+                // quotes without a thread point into the quoting thread unless they're dead
+                let target_thread = match quote.thread {
+                    Some(target_thread) => Some(target_thread),
+                    None if !quote.dead && quote.board.is_none() => Some(thread),
+                    None => None,
+                };
+                query!(
+                    r#"
+                    INSERT OR IGNORE INTO post_links
+                        (board, no, thread, target_board, target_thread, target_post)
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    "#,
+                    board,
+                    post.no,
+                    thread,
+                    target_board,
+                    target_thread,
+                    quote.post,
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await
+    }
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status)
+        .map_or(false, |status| status == reqwest::StatusCode::NOT_FOUND)
+}
+
+/// Stores the perceptual hash of a saved thumbnail for reverse image lookups
+pub async fn hash_thumbnail<S: arkiv_storage::Storage>(
+    pool: &sqlx::SqlitePool,
+    storage: &S,
+    board: &str,
+    tim: i64,
+) -> anyhow::Result<()> {
+    let hashed = query_scalar!(
+        "SELECT count(*) FROM image_hashes WHERE board = ? AND tim = ?",
+        board,
+        tim
+    )
+    .fetch_one(pool)
+    .await?;
+    if hashed > 0 {
+        return Ok(());
     }
+
+    let key = format!("{}s.jpg", tim);
+    let body = match storage.get(&key, Some(board)).await {
+        Ok(body) => body,
+        // no thumbnail could be fetched or generated
+        Err(StorageError::NotFound) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let hash = tokio::task::spawn_blocking(move || arkiv_phash::dhash_bytes(&body)).await??;
+
+    let [chunk0, chunk1, chunk2, chunk3] = arkiv_phash::chunks(hash).map(i64::from);
+    // sqlite integers are signed, the bits are stored as is
+    let hash = i64::from_be_bytes(hash.to_be_bytes());
+    query!(
+        r#"
+        INSERT OR IGNORE INTO image_hashes (board, tim, hash, chunk0, chunk1, chunk2, chunk3)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        board,
+        tim,
+        hash,
+        chunk0,
+        chunk1,
+        chunk2,
+        chunk3,
+    )
+    .execute(pool)
+    .await?;
+    trace!("hashed thumbnail {}", &key);
+
+    Ok(())
 }
@@ -0,0 +1,314 @@
+//! Maintenance commands that work on the database and the storage without the 4chan API
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use arkiv_storage::{Storage, StorageError};
+use chrono::{TimeZone, Utc};
+use fourchan::Post;
+use futures::TryStreamExt;
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+use crate::{
+    archiver::hash_thumbnail,
+    config::{MediaSkipReason, ThreadAction},
+    filter, stats,
+};
+
+/// Applies the migrations the database is missing
+pub async fn migrate(pool: &SqlitePool) -> anyhow::Result<()> {
+    let migrator = sqlx::migrate!("../migrations");
+    migrator
+        .run(pool)
+        .await
+        .context("failed to migrate the database")?;
+    if let Some(latest) = migrator.iter().last() {
+        info!(
+            "database is at version {} ({})",
+            latest.version, latest.description
+        );
+    }
+    Ok(())
+}
+
+/// Checks the database for corruption and that the media of archived posts is in the
+/// storage. Fails if anything is wrong.
+pub async fn verify<S: Storage>(
+    pool: &SqlitePool,
+    storage: &S,
+    board: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut problems = 0;
+
+    let integrity: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(pool)
+        .await?;
+    if integrity != ["ok"] {
+        for line in &integrity {
+            println!("database: {}", line);
+        }
+        problems += integrity.len();
+    }
+
+    let text_only = MediaSkipReason::TextOnly.as_str();
+    let mut files = query!(
+        r#"
+        SELECT p.board, p.tim as "tim!", p.ext as "ext!", p.fsize as "fsize!",
+            (SELECT s.reason FROM skipped_media s WHERE s.board = p.board AND s.tim = p.tim)
+                as "skipped?: String"
+        FROM posts p
+        WHERE p.tim IS NOT NULL AND p.filedeleted = 0 AND p.media_removed = 0
+            AND (?1 IS NULL OR p.board = ?1)
+        ORDER BY p.board, p.no
+        "#,
+        board
+    )
+    .fetch(pool);
+
+    let (mut checked, mut missing_media, mut broken_media, mut missing_thumbnails) = (0, 0, 0, 0);
+    while let Some(file) = files.try_next().await? {
+        checked += 1;
+        let key = format!("{}{}", file.tim, file.ext);
+        if file.skipped.is_none() {
+            match storage.metadata(&key, Some(&file.board)).await {
+                Ok(metadata) if i64::try_from(metadata.len) == Ok(file.fsize) => {}
+                Ok(metadata) => {
+                    println!(
+                        "/{}/{}: {} bytes, expected {}",
+                        file.board, key, metadata.len, file.fsize
+                    );
+                    broken_media += 1;
+                }
+                Err(StorageError::NotFound) => {
+                    println!("/{}/{}: missing", file.board, key);
+                    missing_media += 1;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        // text only threads don't keep thumbnails either
+        let thumbnail_key = format!("{}s.jpg", file.tim);
+        if file.skipped.as_deref() != Some(text_only)
+            && !storage.exists(&thumbnail_key, Some(&file.board)).await?
+        {
+            println!("/{}/{}: missing", file.board, thumbnail_key);
+            missing_thumbnails += 1;
+        }
+    }
+    problems += missing_media + broken_media + missing_thumbnails;
+
+    println!(
+        "checked {} files: {} missing, {} with the wrong size, {} missing thumbnails",
+        checked, missing_media, broken_media, missing_thumbnails
+    );
+    if missing_media + broken_media > 0 {
+        println!("missing media can be downloaded again with `arkiv backfill-media`");
+    }
+    if problems > 0 {
+        anyhow::bail!("found {} problems", problems);
+    }
+    Ok(())
+}
+
+/// Hashes the saved thumbnails that weren't hashed yet, e.g. because they were saved
+/// before the hashes were introduced
+pub async fn hash_thumbnails<S: Storage>(
+    pool: &SqlitePool,
+    storage: &S,
+    board: Option<&str>,
+) -> anyhow::Result<()> {
+    let thumbnails = query!(
+        r#"
+        SELECT p.board, p.tim as "tim!" FROM posts p
+        WHERE p.tim IS NOT NULL AND (?1 IS NULL OR p.board = ?1)
+            AND NOT EXISTS
+                (SELECT 1 FROM image_hashes h WHERE h.board = p.board AND h.tim = p.tim)
+        ORDER BY p.board, p.no
+        "#,
+        board
+    )
+    .fetch_all(pool)
+    .await?;
+    info!("hashing {} thumbnails", thumbnails.len());
+
+    let mut failed = 0;
+    for thumbnail in &thumbnails {
+        if let Err(err) = hash_thumbnail(pool, storage, &thumbnail.board, thumbnail.tim).await {
+            warn!(
+                "failed to hash thumbnail /{}/{}s.jpg: {}",
+                &thumbnail.board, thumbnail.tim, err
+            );
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("failed to hash {} thumbnails", failed);
+    }
+    Ok(())
+}
+
+/// Refreshes the statistics rollups and prints a summary for each board
+pub async fn stats(pool: &SqlitePool, board: Option<&str>) -> anyhow::Result<()> {
+    let queued = query_scalar!(
+        r#"
+        SELECT board FROM stats_queue WHERE ?1 IS NULL OR board = ?1
+        UNION SELECT board FROM stats_rebuild WHERE ?1 IS NULL OR board = ?1
+        "#,
+        board
+    )
+    .fetch_all(pool)
+    .await?;
+    for queued_board in &queued {
+        stats::refresh(pool, queued_board).await?;
+    }
+
+    let boards = query!(
+        r#"
+        SELECT b.board, b.posts, b.refreshed_at,
+            (SELECT coalesce(sum(h.threads), 0) FROM stats_hourly h WHERE h.board = b.board)
+                as "threads!: i64",
+            (SELECT coalesce(sum(h.images), 0) FROM stats_hourly h WHERE h.board = b.board)
+                as "images!: i64",
+            (SELECT coalesce(sum(m.bytes), 0) FROM stats_media m WHERE m.board = b.board)
+                as "bytes!: i64"
+        FROM stats_boards b
+        WHERE ?1 IS NULL OR b.board = ?1
+        ORDER BY b.board
+        "#,
+        board
+    )
+    .fetch_all(pool)
+    .await?;
+    if boards.is_empty() {
+        println!("nothing archived yet");
+        return Ok(());
+    }
+
+    println!(
+        "{:<8} {:>10} {:>8} {:>8} {:>10}  refreshed",
+        "board", "posts", "threads", "images", "media MiB"
+    );
+    for board in boards {
+        println!(
+            "{:<8} {:>10} {:>8} {:>8} {:>10}  {}",
+            format!("/{}/", board.board),
+            board.posts,
+            board.threads,
+            board.images,
+            board.bytes / (1024 * 1024),
+            date(board.refreshed_at)
+        );
+    }
+    Ok(())
+}
+
+fn date(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map_or_else(String::new, |time| {
+            time.format("%Y-%m-%d %H:%M").to_string()
+        })
+}
+
+/// Writes the threads of a board to `output`, one thread per line in the JSON format of
+/// the 4chan API. Hidden posts are left out.
+pub async fn export(
+    pool: &SqlitePool,
+    board: &str,
+    thread: Option<i64>,
+    output: &Path,
+) -> anyhow::Result<()> {
+    let threads = query_scalar!(
+        r#"
+        SELECT no FROM posts
+        WHERE board = ? AND resto = 0 AND hidden = 0 AND (?2 IS NULL OR no = ?2)
+        ORDER BY no
+        "#,
+        board,
+        thread
+    )
+    .fetch_all(pool)
+    .await?;
+    if threads.is_empty() {
+        anyhow::bail!("no threads to export on /{}/", board);
+    }
+
+    let file =
+        File::create(output).with_context(|| format!("failed to create {}", output.display()))?;
+    let mut out = BufWriter::new(file);
+    let mut posts = 0;
+    for no in &threads {
+        let thread = query_as!(
+            Post,
+            r#"
+            SELECT * FROM posts
+            WHERE board = ? AND (no = ?2 OR resto = ?2) AND hidden = 0
+            ORDER BY no
+            "#,
+            board,
+            no
+        )
+        .fetch_all(pool)
+        .await?;
+        posts += thread.len();
+
+        serde_json::to_writer(&mut out, &serde_json::json!({ "posts": thread }))?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+
+    info!(
+        "exported {} threads with {} posts to {}",
+        threads.len(),
+        posts,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Pins a thread so it's always archived, or unpins it so the rules decide again
+pub async fn set_pinned(
+    pool: &SqlitePool,
+    board: &str,
+    no: i64,
+    pinned: bool,
+) -> anyhow::Result<()> {
+    let now = Utc::now().timestamp();
+    if pinned {
+        let action = ThreadAction::Archive.as_str();
+        query!(
+            r#"
+            INSERT INTO tracked_threads (board, no, action, rule, pinned, first_seen, updated_at)
+            VALUES (?, ?, ?, ?, 1, ?, ?)
+            ON CONFLICT(board, no) DO UPDATE
+            SET action = excluded.action, rule = excluded.rule, pinned = 1,
+                updated_at = excluded.updated_at;
+            "#,
+            board,
+            no,
+            action,
+            filter::PINNED,
+            now,
+            now,
+        )
+        .execute(pool)
+        .await?;
+    } else {
+        query!(
+            "UPDATE tracked_threads SET pinned = 0, updated_at = ? WHERE board = ? AND no = ?",
+            now,
+            board,
+            no
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
@@ -1,18 +1,28 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
+use fourchan::{Post, PostAttachment};
 use regex::{Regex, RegexBuilder};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub boards: HashMap<String, BoardConfig>,
+
+    /// Generate thumbnails from saved full media when 4chan doesn't provide one.
+    ///
+    /// Default: disabled
+    #[serde(default)]
+    pub thumbnailer: Option<ThumbnailerConfig>,
 }
 
 impl Config {
     pub async fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let raw_config = tokio::fs::read(path.as_ref())
             .await
-            .context("failed to read config file")?;
+            .with_context(|| format!("failed to read config file {}", path.as_ref().display()))?;
         let config: Self =
             serde_yaml::from_slice(&raw_config).context("failed to deserialize config")?;
 
@@ -32,6 +42,14 @@ fn filter_comment_default() -> bool {
     true
 }
 
+fn thumbnail_max_size_default() -> u32 {
+    250
+}
+
+fn thumbnail_quality_default() -> u8 {
+    85
+}
+
 #[derive(Debug, Clone)]
 pub struct CustomRegex(pub Regex);
 impl<'de> serde::Deserialize<'de> for CustomRegex {
@@ -58,6 +76,22 @@ pub struct BoardConfig {
     #[serde(default = "full_media_default")]
     pub full_media: bool,
 
+    /// Thread selection rules, checked against the opening post. The first matching rule
+    /// decides what happens to the thread. Checked before `filters`.
+    ///
+    /// Skipped threads are checked again on every pass, so conditions like
+    /// `{ replies: { gt: 50 } }` start archiving a thread once it gets popular. Threads that
+    /// are being archived stay tracked even if they stop matching.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+
+    /// Action for threads that match no rule. Only used if `filters` is empty, as the regex
+    /// filters decide every thread themselves.
+    ///
+    /// Default: `archive`
+    #[serde(default)]
+    pub default_action: ThreadAction,
+
     /// Regex Filters
     #[serde(default)]
     pub filters: Vec<CustomRegex>,
@@ -73,4 +107,332 @@ pub struct BoardConfig {
     /// Default: `true`
     #[serde(default = "filter_comment_default")]
     pub filter_comment: bool,
+
+    /// Restricts which full size media files get saved. Only applies if `full_media` is set.
+    ///
+    /// Default: save everything
+    #[serde(default)]
+    pub media: MediaPolicy,
+
+    /// Filters applied to every single post of a saved thread. The first matching
+    /// filter decides what happens to the post.
+    ///
+    /// Filters apply when a post is first saved. Posts that are already archived keep their
+    /// content and media when the filters change.
+    #[serde(default)]
+    pub post_filters: Vec<PostFilter>,
+
+    /// MD5 hashes (as base64, like the 4chan API reports them) of files that must never be
+    /// saved. Posts with these files are kept without their media. Adds to the blocklist
+    /// managed in the web admin pages, which applies to every board; a file on either list
+    /// is blocked.
+    #[serde(default)]
+    pub md5_blocklist: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PostFilter {
+    /// Post field the pattern is matched against
+    pub field: PostField,
+
+    /// Case insensitive regex
+    pub pattern: CustomRegex,
+
+    /// Default: `drop`
+    #[serde(default)]
+    pub action: PostFilterAction,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostField {
+    /// Subject
+    Sub,
+    /// Comment text, without markup
+    Com,
+    Name,
+    Trip,
+    /// Poster ID
+    Id,
+    /// Country code
+    Country,
+    Capcode,
+    Filename,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Rule {
+    /// Shown by `check-config`
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(rename = "match")]
+    pub condition: Condition,
+
+    pub action: ThreadAction,
+}
+
+/// A condition on the opening post of a thread. Written as a single key map, e.g.
+/// `{ sub: "rust" }`, `{ replies: { gt: 100 } }` or `{ any: [{ sub: "a" }, { com: "b" }] }`.
+///
+/// Conditions on a field the post doesn't have never match, e.g. `unique_ips` on archived
+/// threads.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Matches if any of the conditions match
+    Any(Vec<Condition>),
+    /// Matches if all of the conditions match
+    All(Vec<Condition>),
+    Not(Box<Condition>),
+    Sub(CustomRegex),
+    /// Comment text, without markup
+    Com(CustomRegex),
+    Filename(CustomRegex),
+    Name(CustomRegex),
+    Trip(CustomRegex),
+    /// Country code
+    Country(CustomRegex),
+    Capcode(CustomRegex),
+    Replies(NumberCondition),
+    Images(NumberCondition),
+    UniqueIps(NumberCondition),
+}
+
+/// Bounds on a number. All given bounds have to hold.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NumberCondition {
+    pub eq: Option<i64>,
+    pub gt: Option<i64>,
+    pub gte: Option<i64>,
+    pub lt: Option<i64>,
+    pub lte: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadAction {
+    /// Save the thread with media, as configured by `full_media` and `media`
+    #[default]
+    Archive,
+    /// Save the posts of the thread without any media
+    TextOnly,
+    /// Don't save the thread
+    Skip,
+}
+
+impl ThreadAction {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThreadAction::Archive => "archive",
+            ThreadAction::TextOnly => "text_only",
+            ThreadAction::Skip => "skip",
+        }
+    }
+}
+
+impl std::str::FromStr for ThreadAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "archive" => Ok(ThreadAction::Archive),
+            "text_only" => Ok(ThreadAction::TextOnly),
+            "skip" => Ok(ThreadAction::Skip),
+            _ => Err(anyhow::anyhow!("unknown thread action {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PostFilterAction {
+    /// Don't save the post or its media
+    #[default]
+    Drop,
+    /// Save the post without its content, poster information and media
+    Redact,
+    /// Save the post but not its media
+    RemoveMedia,
+}
+
+impl BoardConfig {
+    /// Returns why the full media of `attachment` shouldn't be saved, if it shouldn't.
+    #[must_use]
+    pub fn media_skip_reason(
+        &self,
+        post: &Post,
+        attachment: &PostAttachment,
+    ) -> Option<MediaSkipReason> {
+        if self.full_media {
+            self.media.skip_reason(post, attachment)
+        } else {
+            Some(MediaSkipReason::FullMediaDisabled)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MediaPolicy {
+    /// File extensions to save, with or without the leading dot. Empty allows all.
+    ///
+    /// Default: `[]`
+    #[serde(default)]
+    pub extensions: Vec<String>,
+
+    /// Maximum file size in bytes
+    ///
+    /// Default: no limit
+    #[serde(default)]
+    pub max_fsize: Option<i64>,
+
+    /// Skip files that were posted as spoilers
+    ///
+    /// Default: `false`
+    #[serde(default)]
+    pub skip_spoilers: bool,
+
+    /// Only save the media of the opening post
+    ///
+    /// Default: `false`
+    #[serde(default)]
+    pub op_only: bool,
+
+    /// Minimum image width in pixels
+    ///
+    /// Default: no limit
+    #[serde(default)]
+    pub min_width: Option<i64>,
+
+    /// Minimum image height in pixels
+    ///
+    /// Default: no limit
+    #[serde(default)]
+    pub min_height: Option<i64>,
+}
+
+impl MediaPolicy {
+    #[must_use]
+    pub fn skip_reason(&self, post: &Post, attachment: &PostAttachment) -> Option<MediaSkipReason> {
+        let ext = attachment.ext.trim_start_matches('.');
+        if !self.extensions.is_empty()
+            && !self
+                .extensions
+                .iter()
+                .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext))
+        {
+            return Some(MediaSkipReason::Extension);
+        }
+        if self.max_fsize.map_or(false, |max| attachment.fsize > max) {
+            return Some(MediaSkipReason::FileSize);
+        }
+        if self.skip_spoilers && attachment.spoiler != 0 {
+            return Some(MediaSkipReason::Spoiler);
+        }
+        if self.op_only && post.resto != 0 {
+            return Some(MediaSkipReason::NotOp);
+        }
+        if self.min_width.map_or(false, |min| attachment.w < min)
+            || self.min_height.map_or(false, |min| attachment.h < min)
+        {
+            return Some(MediaSkipReason::Dimensions);
+        }
+
+        None
+    }
+}
+
+/// Why the full media of an attachment wasn't saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaSkipReason {
+    FullMediaDisabled,
+    TextOnly,
+    Extension,
+    FileSize,
+    Spoiler,
+    NotOp,
+    Dimensions,
+}
+
+impl MediaSkipReason {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaSkipReason::FullMediaDisabled => "full_media_disabled",
+            MediaSkipReason::TextOnly => "text_only",
+            MediaSkipReason::Extension => "extension",
+            MediaSkipReason::FileSize => "file_size",
+            MediaSkipReason::Spoiler => "spoiler",
+            MediaSkipReason::NotOp => "not_op",
+            MediaSkipReason::Dimensions => "dimensions",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ThumbnailerConfig {
+    /// Maximum width and height of generated thumbnails in pixels
+    ///
+    /// Default: `250`
+    #[serde(default = "thumbnail_max_size_default")]
+    pub max_size: u32,
+
+    /// JPEG quality of generated thumbnails, from 1 to 100
+    ///
+    /// Default: `85`
+    #[serde(default = "thumbnail_quality_default")]
+    pub quality: u8,
+
+    /// Path to an `ffmpeg` binary used to extract the first frame of webm and mp4 files.
+    /// Video attachments don't get thumbnails if unset.
+    #[serde(default)]
+    pub ffmpeg: Option<PathBuf>,
+}
+
+#[test]
+fn test_media_policy() {
+    use MediaSkipReason::{Dimensions, Extension, FileSize, NotOp, Spoiler};
+
+    let board_cfg: BoardConfig = serde_yaml::from_str(
+        r#"
+        media:
+          extensions: [jpg, ".PNG"]
+          max_fsize: 1000
+          skip_spoilers: true
+          op_only: true
+          min_width: 100
+          min_height: 100
+        "#,
+    )
+    .unwrap();
+
+    // resto, ext, fsize, spoiler, w, h
+    let cases = [
+        ((0, ".jpg", 1000, 0, 100, 100), None),
+        ((0, ".png", 1000, 0, 100, 100), None),
+        ((0, ".webm", 1000, 0, 100, 100), Some(Extension)),
+        ((0, ".jpg", 1001, 0, 100, 100), Some(FileSize)),
+        ((0, ".jpg", 1000, 1, 100, 100), Some(Spoiler)),
+        ((1, ".jpg", 1000, 0, 100, 100), Some(NotOp)),
+        ((0, ".jpg", 1000, 0, 99, 100), Some(Dimensions)),
+        ((0, ".jpg", 1000, 0, 100, 99), Some(Dimensions)),
+    ];
+    for (fields, expected) in cases {
+        let (resto, ext, fsize, spoiler, w, h) = fields;
+        let post: Post = serde_json::from_value(serde_json::json!({
+            "no": 2, "resto": resto, "now": "", "time": 0, "tim": 1, "filename": "f",
+            "ext": ext, "fsize": fsize, "md5": "m", "w": w, "h": h, "tn_w": 1, "tn_h": 1,
+            "spoiler": spoiler,
+        }))
+        .unwrap();
+        let attachment = post.attachment().unwrap();
+        assert_eq!(
+            board_cfg.media_skip_reason(&post, &attachment),
+            expected,
+            "{:?}",
+            fields
+        );
+    }
 }
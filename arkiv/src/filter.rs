@@ -0,0 +1,345 @@
+use fourchan::{Comment, Post};
+
+use crate::config::{
+    BoardConfig, Condition, Config, CustomRegex, NumberCondition, PostField, PostFilterAction,
+    ThreadAction,
+};
+
+/// Extracts the text of an HTML comment, dropping all markup
+#[must_use]
+pub fn comment_text(com: &str) -> String {
+    Comment::parse(com).plain_text()
+}
+
+impl PostField {
+    /// Returns the value of the field in `post`, if the post has one
+    #[must_use]
+    pub fn value(self, post: &Post) -> Option<String> {
+        match self {
+            PostField::Sub => post.sub.clone(),
+            PostField::Com => post.com.as_deref().map(comment_text),
+            PostField::Name => Some(post.name.clone()),
+            PostField::Trip => post.trip.clone(),
+            PostField::Id => post.id.clone(),
+            PostField::Country => post.country.clone(),
+            PostField::Capcode => post.capcode.clone(),
+            PostField::Filename => post.filename.clone(),
+        }
+    }
+}
+
+impl NumberCondition {
+    #[must_use]
+    pub fn matches(&self, value: i64) -> bool {
+        self.eq.map_or(true, |eq| value == eq)
+            && self.gt.map_or(true, |gt| value > gt)
+            && self.gte.map_or(true, |gte| value >= gte)
+            && self.lt.map_or(true, |lt| value < lt)
+            && self.lte.map_or(true, |lte| value <= lte)
+    }
+}
+
+impl Condition {
+    /// Checks the condition against the opening post of a thread
+    #[must_use]
+    pub fn matches(&self, op: &Post) -> bool {
+        let field_matches = |field: PostField, CustomRegex(regex): &CustomRegex| {
+            field
+                .value(op)
+                .map_or(false, |value| regex.is_match(&value))
+        };
+
+        match self {
+            Condition::Any(conditions) => conditions.iter().any(|c| c.matches(op)),
+            Condition::All(conditions) => conditions.iter().all(|c| c.matches(op)),
+            Condition::Not(condition) => !condition.matches(op),
+            Condition::Sub(regex) => field_matches(PostField::Sub, regex),
+            Condition::Com(regex) => field_matches(PostField::Com, regex),
+            Condition::Filename(regex) => field_matches(PostField::Filename, regex),
+            Condition::Name(regex) => field_matches(PostField::Name, regex),
+            Condition::Trip(regex) => field_matches(PostField::Trip, regex),
+            Condition::Country(regex) => field_matches(PostField::Country, regex),
+            Condition::Capcode(regex) => field_matches(PostField::Capcode, regex),
+            Condition::Replies(number) => op.replies.map_or(false, |n| number.matches(n)),
+            Condition::Images(number) => op.images.map_or(false, |n| number.matches(n)),
+            Condition::UniqueIps(number) => op.unique_ips.map_or(false, |n| number.matches(n)),
+        }
+    }
+}
+
+/// Decides what happens to a thread based on its opening post. Returns the action and the
+/// name of the rule that decided it, if any.
+#[must_use]
+pub fn thread_action<'a>(board_cfg: &'a BoardConfig, op: &Post) -> (ThreadAction, Option<&'a str>) {
+    if let Some(rule) = board_cfg
+        .rules
+        .iter()
+        .find(|rule| rule.condition.matches(op))
+    {
+        return (
+            rule.action,
+            Some(rule.name.as_deref().unwrap_or("unnamed rule")),
+        );
+    }
+
+    if !board_cfg.filters.is_empty() {
+        let filter_match = board_cfg.filters.iter().any(|CustomRegex(filter)| {
+            op.sub.as_ref().map_or(false, |sub| filter.is_match(sub))
+                || (board_cfg.filter_comment
+                    && op
+                        .com
+                        .as_ref()
+                        .map_or(false, |com| filter.is_match(&comment_text(com))))
+        });
+        let action = if filter_match ^ board_cfg.reverse_filter {
+            ThreadAction::Skip
+        } else {
+            ThreadAction::Archive
+        };
+        return (action, Some("filters"));
+    }
+
+    (board_cfg.default_action, None)
+}
+
+/// The rule stored for threads archived because they're pinned
+pub const PINNED: &str = "pinned";
+
+/// Combines the decision of the rules with the action stored for the thread on an earlier
+/// pass. Pinned threads are always archived, and threads that were archived stay tracked
+/// even if the rules would skip them now, e.g. because the subject was edited.
+#[must_use]
+pub fn tracked_action<'a>(
+    rules: (ThreadAction, Option<&'a str>),
+    previous: Option<ThreadAction>,
+    pinned: bool,
+) -> (ThreadAction, Option<&'a str>) {
+    match previous {
+        _ if pinned => (ThreadAction::Archive, Some(PINNED)),
+        Some(previous) if rules.0 == ThreadAction::Skip && previous != ThreadAction::Skip => {
+            (previous, Some("tracked"))
+        }
+        _ => rules,
+    }
+}
+
+/// Decides what happens to a single post based on the board's post filters. Returns `None`
+/// if the post should be saved as is.
+#[must_use]
+pub fn post_action(board_cfg: &BoardConfig, post: &Post) -> Option<PostFilterAction> {
+    for filter in &board_cfg.post_filters {
+        if let Some(value) = filter.field.value(post) {
+            if filter.pattern.0.is_match(&value) {
+                return Some(filter.action);
+            }
+        }
+    }
+    None
+}
+
+/// Strips the content, poster information and attachment from a post. Keeps the post
+/// number and timestamps, so the thread structure stays intact.
+pub fn redact(post: &mut Post) {
+    post.name = "Anonymous".to_string();
+    post.trip = None;
+    post.id = None;
+    post.country = None;
+    post.country_name = None;
+    post.board_flag = None;
+    post.flag_name = None;
+    post.sub = None;
+    post.com = None;
+    post.tim = None;
+    post.filename = None;
+    post.ext = None;
+    post.fsize = None;
+    post.md5 = None;
+    post.w = None;
+    post.h = None;
+    post.tn_w = None;
+    post.tn_h = None;
+}
+
+/// Dry-runs the thread selection of every configured board against the live catalog and
+/// prints the action taken for each thread.
+pub async fn check_config(config: &Config, only_board: Option<&str>) -> anyhow::Result<()> {
+    let client = fourchan::Client::new();
+
+    let mut boards: Vec<_> = config
+        .boards
+        .iter()
+        .filter(|(name, _)| only_board.map_or(true, |only| only == name.as_str()))
+        .collect();
+    if boards.is_empty() {
+        anyhow::bail!("no matching board in config");
+    }
+    boards.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (board, board_cfg) in boards {
+        println!("/{}/", board);
+        let (mut archive, mut text_only, mut skip) = (0, 0, 0);
+
+        for page in client.get_catalog(board).await?.0 {
+            for op in page.threads {
+                let (action, rule) = thread_action(board_cfg, &op);
+                match action {
+                    ThreadAction::Archive => archive += 1,
+                    ThreadAction::TextOnly => text_only += 1,
+                    ThreadAction::Skip => skip += 1,
+                }
+
+                let title = op
+                    .sub
+                    .clone()
+                    .or_else(|| op.com.as_deref().map(comment_text))
+                    .unwrap_or_default();
+                println!(
+                    "  {:>10}  {:<9}  {:<16}  {}",
+                    op.no,
+                    action.as_str(),
+                    rule.unwrap_or("default"),
+                    title.chars().take(60).collect::<String>()
+                );
+            }
+        }
+
+        println!(
+            "  {} archived, {} text only, {} skipped\n",
+            archive, text_only, skip
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_rules() {
+    let board_cfg: BoardConfig = serde_yaml::from_str(
+        r#"
+        rules:
+          - match: { all: [{ sub: "rust" }, { not: { replies: { lt: 10 } } }] }
+            action: archive
+          - match: { any: [{ com: "general" }, { country: "^XX$" }] }
+            action: text_only
+        default_action: skip
+        "#,
+    )
+    .unwrap();
+    let op = |json: &str| -> Post { serde_json::from_str(json).unwrap() };
+
+    let action = |json| thread_action(&board_cfg, &op(json)).0;
+    assert_eq!(
+        action(r#"{"no": 1, "resto": 0, "now": "", "time": 0, "sub": "Rust", "replies": 20}"#),
+        ThreadAction::Archive
+    );
+    assert_eq!(
+        action(r#"{"no": 1, "resto": 0, "now": "", "time": 0, "sub": "rust", "replies": 5}"#),
+        ThreadAction::Skip
+    );
+    assert_eq!(
+        action(r#"{"no": 1, "resto": 0, "now": "", "time": 0, "com": "/g/ <b>general</b>"}"#),
+        ThreadAction::TextOnly
+    );
+    // a missing count matches no bounds, so `not` matches
+    assert_eq!(
+        action(r#"{"no": 1, "resto": 0, "now": "", "time": 0, "sub": "rust"}"#),
+        ThreadAction::Archive
+    );
+}
+
+#[test]
+fn test_post_filters() {
+    let board_cfg: BoardConfig = serde_yaml::from_str(
+        r#"
+        post_filters:
+          - field: com
+            pattern: "buy now"
+          - field: trip
+            pattern: "^!spammer$"
+            action: redact
+          - field: filename
+            pattern: "^gore"
+            action: remove_media
+        "#,
+    )
+    .unwrap();
+    let post = |json: &str| -> Post { serde_json::from_str(json).unwrap() };
+
+    let action = |json| post_action(&board_cfg, &post(json));
+    assert_eq!(
+        action(r#"{"no": 2, "resto": 1, "now": "", "time": 0, "com": "<b>BUY</b> NOW"}"#),
+        Some(PostFilterAction::Drop)
+    );
+    assert_eq!(
+        action(r#"{"no": 2, "resto": 1, "now": "", "time": 0, "trip": "!spammer"}"#),
+        Some(PostFilterAction::Redact)
+    );
+    assert_eq!(
+        action(r#"{"no": 2, "resto": 1, "now": "", "time": 0, "filename": "gore1"}"#),
+        Some(PostFilterAction::RemoveMedia)
+    );
+    assert_eq!(
+        action(r#"{"no": 2, "resto": 1, "now": "", "time": 0, "com": "hello"}"#),
+        None
+    );
+
+    let mut redacted = post(
+        r#"{"no": 2, "resto": 1, "now": "", "time": 5, "name": "x", "trip": "!spammer",
+            "sub": "s", "com": "c", "tim": 123, "filename": "f", "ext": ".jpg", "fsize": 1,
+            "md5": "m", "w": 1, "h": 1, "tn_w": 1, "tn_h": 1}"#,
+    );
+    redact(&mut redacted);
+    assert_eq!((redacted.no, redacted.resto, redacted.time), (2, 1, 5));
+    assert_eq!(redacted.name, "Anonymous");
+    assert!(redacted.trip.is_none() && redacted.sub.is_none() && redacted.com.is_none());
+    assert!(redacted.attachment().is_none() && redacted.md5.is_none());
+}
+
+#[test]
+fn test_tracked_action() {
+    let board_cfg: BoardConfig = serde_yaml::from_str(
+        r#"
+        rules:
+          - name: popular
+            match: { replies: { gt: 10 } }
+            action: archive
+        default_action: skip
+        "#,
+    )
+    .unwrap();
+    let op = |replies: i64| -> Post {
+        serde_json::from_value(serde_json::json!({
+            "no": 1, "resto": 0, "now": "", "time": 0, "replies": replies,
+        }))
+        .unwrap()
+    };
+    let rules = |replies| thread_action(&board_cfg, &op(replies));
+
+    // a new thread is skipped until it gets enough replies
+    assert_eq!(
+        tracked_action(rules(3), None, false),
+        (ThreadAction::Skip, None)
+    );
+    assert_eq!(
+        tracked_action(rules(11), Some(ThreadAction::Skip), false),
+        (ThreadAction::Archive, Some("popular"))
+    );
+    // and then stays tracked even if the rules skip it
+    assert_eq!(
+        tracked_action(rules(3), Some(ThreadAction::Archive), false),
+        (ThreadAction::Archive, Some("tracked"))
+    );
+    assert_eq!(
+        tracked_action(rules(3), Some(ThreadAction::TextOnly), false),
+        (ThreadAction::TextOnly, Some("tracked"))
+    );
+    // pinned threads are archived whatever the rules say
+    assert_eq!(
+        tracked_action(rules(3), None, true),
+        (ThreadAction::Archive, Some(PINNED))
+    );
+    assert_eq!(
+        tracked_action(rules(3), Some(ThreadAction::TextOnly), true),
+        (ThreadAction::Archive, Some(PINNED))
+    );
+}
@@ -3,9 +3,16 @@
 #![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
 
 use anyhow::Context;
-use archiver::Archiver;
-use config::Config;
+use archiver::{Archiver, BackfillFilter};
 use arkiv_storage::local::LocalStorage;
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+use config::{Config, ThreadAction};
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[macro_use]
 extern crate serde;
@@ -13,24 +20,253 @@ extern crate serde;
 extern crate sqlx;
 
 pub mod archiver;
+pub mod cli;
 pub mod config;
+pub mod filter;
+pub mod stats;
+pub mod thumbnailer;
+
+#[derive(Debug, Parser)]
+#[clap(version, about)]
+struct Cli {
+    /// Config file with the boards to archive
+    #[clap(long, global = true, env = "CONFIG_PATH", default_value = "config.yml")]
+    config: PathBuf,
+
+    /// Database to archive into, e.g. `sqlite://database.sq3`
+    #[clap(long, global = true, env = "DATABASE_URL")]
+    database_url: Option<String>,
+
+    /// Directory the media is saved in
+    #[clap(long, global = true, env = "DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Don't apply database migrations before running the command
+    #[clap(long, global = true)]
+    no_migrate: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Archive the configured boards until stopped. This is the default
+    Run,
+    /// Archive a single thread once. The configured rules still apply, pin the thread to
+    /// archive it regardless
+    ArchiveThread(ThreadArgs),
+    /// Apply the database migrations that haven't been applied yet. The other commands do
+    /// this as well, unless --no-migrate is given
+    Migrate,
+    /// Download full media of archived posts that is missing from the storage
+    BackfillMedia(BackfillArgs),
+    /// Hash saved thumbnails for the image search that weren't hashed when they were saved
+    HashThumbnails(BoardArgs),
+    /// Check the database and that the media of archived posts is in the storage
+    Verify(BoardArgs),
+    /// Validate the config and dry-run the thread filters against the live catalog
+    CheckConfig(BoardArgs),
+    /// Refresh the statistics and print a summary of each board
+    Stats(BoardArgs),
+    /// Write the threads of a board to a file, one thread per line in the 4chan API format
+    Export(ExportArgs),
+    /// Always archive a thread, regardless of the configured rules
+    Pin(ThreadArgs),
+    /// Remove a pin, so the configured rules decide about the thread again
+    Unpin(ThreadArgs),
+}
+
+#[derive(Debug, Args)]
+struct ThreadArgs {
+    board: String,
+    no: i64,
+}
+
+#[derive(Debug, Args)]
+struct BoardArgs {
+    /// Only this board
+    #[clap(long)]
+    board: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    board: String,
+    output: PathBuf,
+
+    /// Only export this thread
+    #[clap(long)]
+    thread: Option<i64>,
+}
+
+#[derive(Debug, Args)]
+struct BackfillArgs {
+    /// Only backfill posts on this board
+    #[clap(long)]
+    board: Option<String>,
+
+    /// Only backfill posts made on or after this date (YYYY-MM-DD)
+    #[clap(long)]
+    since: Option<NaiveDate>,
+
+    /// Only backfill posts made before this date (YYYY-MM-DD)
+    #[clap(long)]
+    until: Option<NaiveDate>,
+
+    /// Only backfill files with this extension. Can be given multiple times
+    #[clap(long = "ext")]
+    extensions: Vec<String>,
+
+    /// Also download media that the board's media policy skips
+    #[clap(long)]
+    ignore_policy: bool,
+
+    /// Download at most this many files
+    #[clap(long)]
+    limit: Option<usize>,
+
+    /// List missing files without downloading them
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Maximum number of downloads per second, at most 10
+    #[clap(long, default_value = "1", value_parser = parse_rate)]
+    rate: f64,
+}
+
+/// Download rates slow enough not to hammer the 4chan media servers
+fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value.parse().map_err(|_| format!("{} is not a number", value))?;
+    if rate.is_finite() && rate > 0.0 && rate <= 10.0 {
+        Ok(rate)
+    } else {
+        Err("has to be greater than 0 and at most 10".to_string())
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> Option<i64> {
+    date.and_hms_opt(0, 0, 0).map(|datetime| datetime.timestamp())
+}
+
+impl From<BackfillArgs> for BackfillFilter {
+    fn from(args: BackfillArgs) -> Self {
+        BackfillFilter {
+            board: args.board,
+            since: args.since.and_then(start_of_day),
+            until: args.until.and_then(start_of_day),
+            extensions: args.extensions,
+            ignore_policy: args.ignore_policy,
+            limit: args.limit,
+            dry_run: args.dry_run,
+            rate: args.rate,
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    dotenv::dotenv().unwrap();
+    // flags take precedence over the environment, which may be set without a .env file
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
     tracing_subscriber::fmt::init();
 
-    let config_path = std::env::var_os("CONFIG_PATH").unwrap_or_else(|| "config.yml".into());
-    let config = Config::load(&config_path).await?;
+    let migrate = !cli.no_migrate;
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => {
+            let archiver = archiver(&cli.config, cli.database_url, cli.data_dir, migrate).await?;
+            archiver.run().await
+        }
+        Command::ArchiveThread(args) => {
+            let archiver = archiver(&cli.config, cli.database_url, cli.data_dir, migrate).await?;
+            let board_cfg = archiver
+                .config()
+                .boards
+                .get(&args.board)
+                .with_context(|| format!("/{}/ is not in the config", args.board))?
+                .clone();
+            let action = archiver
+                .archive_thread(&args.board, &board_cfg, args.no)
+                .await?;
+            archiver.wait().await?;
+            match action {
+                Some(ThreadAction::Skip) => {
+                    anyhow::bail!("the rules skip the thread, pin it to archive it anyway")
+                }
+                Some(_) => Ok(()),
+                None => anyhow::bail!("the thread could not be fetched"),
+            }
+        }
+        Command::Migrate => {
+            connect(cli.database_url, true).await?;
+            Ok(())
+        }
+        Command::BackfillMedia(args) => {
+            let archiver = archiver(&cli.config, cli.database_url, cli.data_dir, migrate).await?;
+            archiver.backfill_media(&args.into()).await
+        }
+        Command::HashThumbnails(args) => {
+            let pool = connect(cli.database_url, migrate).await?;
+            cli::hash_thumbnails(&pool, &storage(cli.data_dir)?, args.board.as_deref()).await
+        }
+        Command::Verify(args) => {
+            let pool = connect(cli.database_url, migrate).await?;
+            cli::verify(&pool, &storage(cli.data_dir)?, args.board.as_deref()).await
+        }
+        Command::CheckConfig(args) => {
+            let config = Config::load(&cli.config).await?;
+            filter::check_config(&config, args.board.as_deref()).await
+        }
+        Command::Stats(args) => {
+            let pool = connect(cli.database_url, migrate).await?;
+            cli::stats(&pool, args.board.as_deref()).await
+        }
+        Command::Export(args) => {
+            let pool = connect(cli.database_url, migrate).await?;
+            cli::export(&pool, &args.board, args.thread, &args.output).await
+        }
+        Command::Pin(args) => {
+            let pool = connect(cli.database_url, migrate).await?;
+            cli::set_pinned(&pool, &args.board, args.no, true).await
+        }
+        Command::Unpin(args) => {
+            let pool = connect(cli.database_url, migrate).await?;
+            cli::set_pinned(&pool, &args.board, args.no, false).await
+        }
+    }
+}
 
+/// Connects to the database and brings its schema up to date, unless `migrate` is false
+async fn connect(database_url: Option<String>, migrate: bool) -> anyhow::Result<SqlitePool> {
+    let database_url =
+        database_url.context("no database given, use --database-url or DATABASE_URL")?;
+    // a fresh deployment starts with an empty database
+    let options = SqliteConnectOptions::from_str(&database_url)
+        .context("invalid database url")?
+        .create_if_missing(migrate);
     let pool = sqlx::sqlite::SqlitePoolOptions::new()
         .max_connections(10)
-        .connect(&std::env::var("DATABASE_URL").context("missing db env var")?)
+        .connect_with(options)
         .await
         .context("failed to connect to database")?;
+    if migrate {
+        cli::migrate(&pool).await?;
+    }
+    Ok(pool)
+}
 
-    let data_dir = std::env::var_os("DATA_DIR").context("missing data dir var")?;
-    let storage = LocalStorage::new(&data_dir);
+fn storage(data_dir: Option<PathBuf>) -> anyhow::Result<LocalStorage> {
+    let data_dir = data_dir.context("no data directory given, use --data-dir or DATA_DIR")?;
+    Ok(LocalStorage::new(data_dir))
+}
 
-    Archiver::new(pool, storage, config).run().await
+async fn archiver(
+    config_path: &Path,
+    database_url: Option<String>,
+    data_dir: Option<PathBuf>,
+    migrate: bool,
+) -> anyhow::Result<Archiver<LocalStorage>> {
+    let config = Config::load(config_path).await?;
+    let pool = connect(database_url, migrate).await?;
+    Ok(Archiver::new(pool, storage(data_dir)?, config))
 }
@@ -0,0 +1,223 @@
+//! Rollup tables behind the statistics pages.
+//!
+//! Inserting a post queues it in `stats_queue`. [`refresh`] folds the queued posts into
+//! the rollups, so the pages never have to aggregate the whole `posts` table.
+//!
+//! Hidden posts aren't counted. Deleting or (un)hiding a counted post can't be folded in,
+//! so it marks the board in `stats_rebuild` and the next refresh counts the board again.
+
+use chrono::Utc;
+use tracing::debug;
+
+/// Adds the posts queued since the last refresh to the rollups of `board`
+#[allow(clippy::too_many_lines)]
+pub async fn refresh(pool: &sqlx::SqlitePool, board: &str) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let rebuild = query!("DELETE FROM stats_rebuild WHERE board = ?", board)
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+        > 0;
+    if rebuild {
+        reset(&mut tx, board).await?;
+    }
+
+    // posts queued while the refresh runs are left for the next one
+    let last_id = query_scalar!(
+        r#"SELECT max(id) as "id?: i64" FROM stats_queue WHERE board = ?"#,
+        board
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    let last_id = match last_id {
+        Some(last_id) => last_id,
+        None => {
+            tx.commit().await?;
+            return Ok(());
+        }
+    };
+    let now = Utc::now().timestamp();
+
+    query!(
+        r#"
+        INSERT INTO stats_boards (board, posts, min_no, max_no, refreshed_at)
+        SELECT p.board, count(*), min(p.no), max(p.no), ?
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0
+        GROUP BY p.board
+        ON CONFLICT (board) DO UPDATE SET
+            posts = posts + excluded.posts,
+            min_no = min(min_no, excluded.min_no),
+            max_no = max(max_no, excluded.max_no),
+            refreshed_at = excluded.refreshed_at
+        "#,
+        now,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        r#"
+        INSERT INTO stats_hourly (board, hour, posts, threads, images)
+        SELECT p.board, p.time / 3600 * 3600 as hour, count(*),
+            count(CASE WHEN p.resto = 0 THEN 1 END), count(p.tim)
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0
+        GROUP BY hour
+        ON CONFLICT (board, hour) DO UPDATE SET
+            posts = posts + excluded.posts,
+            threads = threads + excluded.threads,
+            images = images + excluded.images
+        "#,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        r#"
+        INSERT INTO stats_countries (board, country, country_name, posts)
+        SELECT p.board, p.country, max(p.country_name), count(*)
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0 AND p.country IS NOT NULL
+        GROUP BY p.country
+        ON CONFLICT (board, country) DO UPDATE SET
+            country_name = ifnull(excluded.country_name, country_name),
+            posts = posts + excluded.posts
+        "#,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        r#"
+        INSERT INTO stats_flags (board, flag, flag_name, posts)
+        SELECT p.board, p.board_flag, max(p.flag_name), count(*)
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0 AND p.board_flag IS NOT NULL
+        GROUP BY p.board_flag
+        ON CONFLICT (board, flag) DO UPDATE SET
+            flag_name = ifnull(excluded.flag_name, flag_name),
+            posts = posts + excluded.posts
+        "#,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        r#"
+        INSERT INTO stats_trips (board, trip, posts, first_post, last_post)
+        SELECT p.board, p.trip, count(*), min(p.time), max(p.time)
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0 AND p.trip IS NOT NULL
+        GROUP BY p.trip
+        ON CONFLICT (board, trip) DO UPDATE SET
+            posts = posts + excluded.posts,
+            first_post = min(first_post, excluded.first_post),
+            last_post = max(last_post, excluded.last_post)
+        "#,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        r#"
+        INSERT INTO stats_media (board, ext, files, bytes)
+        SELECT p.board, lower(p.ext), count(*), ifnull(sum(p.fsize), 0)
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0 AND p.ext IS NOT NULL
+        GROUP BY lower(p.ext)
+        ON CONFLICT (board, ext) DO UPDATE SET
+            files = files + excluded.files,
+            bytes = bytes + excluded.bytes
+        "#,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        r#"
+        INSERT INTO stats_threads (board, no, created, last_post, posts)
+        SELECT p.board, CASE p.resto WHEN 0 THEN p.no ELSE p.resto END as thread,
+            min(p.time), max(p.time), count(*)
+        FROM stats_queue q JOIN posts p ON p.no = q.no AND p.board = q.board
+        WHERE q.board = ? AND q.id <= ? AND p.hidden = 0
+        GROUP BY thread
+        ON CONFLICT (board, no) DO UPDATE SET
+            created = min(created, excluded.created),
+            last_post = max(last_post, excluded.last_post),
+            posts = posts + excluded.posts
+        "#,
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let counted = query!(
+        "DELETE FROM stats_queue WHERE board = ? AND id <= ?",
+        board,
+        last_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+
+    tx.commit().await?;
+    debug!("added {} posts to the stats of /{}/", counted, board);
+
+    Ok(())
+}
+
+/// Empties the rollups of `board` and queues all of its visible posts again
+async fn reset(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, board: &str) -> sqlx::Result<()> {
+    query!("DELETE FROM stats_boards WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_hourly WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_countries WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_flags WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_trips WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_media WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_threads WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM stats_queue WHERE board = ?", board)
+        .execute(&mut *tx)
+        .await?;
+    let queued = query!(
+        r#"
+        INSERT INTO stats_queue (board, no)
+        SELECT board, no FROM posts WHERE board = ? AND hidden = 0 ORDER BY no
+        "#,
+        board
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    debug!("rebuilding the stats of /{}/ from {} posts", board, queued);
+
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use std::{
+    io::Cursor,
+    path::PathBuf,
+    process::Command,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use image::{codecs::jpeg::JpegEncoder, ColorType, DynamicImage};
+
+use crate::config::ThumbnailerConfig;
+
+const IMAGE_EXTENSIONS: &[&str] = &[".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp"];
+const VIDEO_EXTENSIONS: &[&str] = &[".webm", ".mp4"];
+
+/// Extracts a still frame from video attachments so a thumbnail can be made from it
+pub trait FrameDecoder: Send + Sync {
+    fn first_frame(&self, body: &[u8], ext: &str) -> anyhow::Result<DynamicImage>;
+}
+
+/// Decodes frames by shelling out to an `ffmpeg` binary
+#[derive(Debug, Clone)]
+pub struct FfmpegDecoder {
+    binary: PathBuf,
+}
+
+impl FfmpegDecoder {
+    pub fn new<P: Into<PathBuf>>(binary: P) -> Self {
+        Self {
+            binary: binary.into(),
+        }
+    }
+}
+
+impl FrameDecoder for FfmpegDecoder {
+    fn first_frame(&self, body: &[u8], ext: &str) -> anyhow::Result<DynamicImage> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        // mp4 files can keep their index at the end, so ffmpeg needs a seekable input
+        let input = std::env::temp_dir().join(format!(
+            "arkiv-frame-{}-{}{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            ext
+        ));
+        std::fs::write(&input, body).context("failed to write temporary video file")?;
+
+        let output = Command::new(&self.binary)
+            .args(["-v", "error", "-i"])
+            .arg(&input)
+            .args([
+                "-frames:v",
+                "1",
+                "-f",
+                "image2pipe",
+                "-vcodec",
+                "png",
+                "pipe:1",
+            ])
+            .output();
+        let _ = std::fs::remove_file(&input);
+        let output = output.context("failed to run ffmpeg")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "ffmpeg exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        image::load_from_memory(&output.stdout).context("failed to decode extracted frame")
+    }
+}
+
+#[derive(Debug)]
+pub struct Thumbnail {
+    pub body: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Generates jpeg thumbnails from full size media
+#[derive(Clone)]
+pub struct Thumbnailer {
+    max_size: u32,
+    quality: u8,
+    decoder: Option<Arc<dyn FrameDecoder>>,
+}
+
+impl Thumbnailer {
+    #[must_use]
+    pub fn new(config: &ThumbnailerConfig) -> Self {
+        let decoder = config
+            .ffmpeg
+            .as_ref()
+            .map(|binary| Arc::new(FfmpegDecoder::new(binary)) as Arc<dyn FrameDecoder>);
+
+        Self {
+            max_size: config.max_size,
+            quality: config.quality,
+            decoder,
+        }
+    }
+
+    /// Replaces the decoder used for video attachments
+    #[must_use]
+    pub fn with_decoder(mut self, decoder: Arc<dyn FrameDecoder>) -> Self {
+        self.decoder = Some(decoder);
+        self
+    }
+
+    /// Whether a thumbnail can be generated for files with the extension `ext`
+    #[must_use]
+    pub fn supports(&self, ext: &str) -> bool {
+        let ext = ext.to_lowercase();
+        IMAGE_EXTENSIONS.contains(&ext.as_str())
+            || (self.decoder.is_some() && VIDEO_EXTENSIONS.contains(&ext.as_str()))
+    }
+
+    pub async fn generate(&self, body: Bytes, ext: &str) -> anyhow::Result<Thumbnail> {
+        let thumbnailer = self.clone();
+        let ext = ext.to_lowercase();
+        tokio::task::spawn_blocking(move || thumbnailer.generate_blocking(&body, &ext)).await?
+    }
+
+    fn generate_blocking(&self, body: &[u8], ext: &str) -> anyhow::Result<Thumbnail> {
+        let image = if VIDEO_EXTENSIONS.contains(&ext) {
+            self.decoder
+                .as_ref()
+                .context("no frame decoder configured")?
+                .first_frame(body, ext)?
+        } else {
+            image::load_from_memory(body).context("failed to decode image")?
+        };
+
+        let thumbnail = image.thumbnail(self.max_size, self.max_size).to_rgb8();
+        let (width, height) = thumbnail.dimensions();
+
+        let mut body = Cursor::new(Vec::new());
+        JpegEncoder::new_with_quality(&mut body, self.quality)
+            .encode(&thumbnail, width, height, ColorType::Rgb8)
+            .context("failed to encode thumbnail")?;
+
+        Ok(Thumbnail {
+            body: body.into_inner(),
+            width,
+            height,
+        })
+    }
+}
@@ -0,0 +1,101 @@
+//! Perceptual hashes for finding resized or re-encoded copies of an image.
+//!
+//! Hashes are indexed by splitting them into four 16 bit chunks. Two hashes within
+//! distance `d` share at least one chunk within distance `d / 4`, so candidates can be
+//! found with exact lookups on the chunks and their close neighbors.
+#![warn(clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
+
+use image::{imageops::FilterType, DynamicImage};
+
+/// Number of chunks a hash is indexed by
+pub const CHUNKS: u32 = 4;
+
+/// Largest distance [`chunk_neighbors`] can be searched with before the candidate lists
+/// get too long to be useful
+pub const MAX_DISTANCE: u32 = 11;
+
+/// Computes the 64 bit difference hash of an image
+#[must_use]
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Decodes an encoded image and computes its difference hash
+pub fn dhash_bytes(body: &[u8]) -> image::ImageResult<u64> {
+    Ok(dhash(&image::load_from_memory(body)?))
+}
+
+/// Number of bits that differ between two hashes
+#[must_use]
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Splits a hash into the chunks it's indexed by, most significant first
+#[must_use]
+pub fn chunks(hash: u64) -> [u16; 4] {
+    let bytes = hash.to_be_bytes();
+    [
+        u16::from_be_bytes([bytes[0], bytes[1]]),
+        u16::from_be_bytes([bytes[2], bytes[3]]),
+        u16::from_be_bytes([bytes[4], bytes[5]]),
+        u16::from_be_bytes([bytes[6], bytes[7]]),
+    ]
+}
+
+/// All chunk values within `radius` bits of `chunk`, including `chunk` itself
+#[must_use]
+pub fn chunk_neighbors(chunk: u16, radius: u32) -> Vec<u16> {
+    fn flip(value: u16, from_bit: u32, radius: u32, out: &mut Vec<u16>) {
+        out.push(value);
+        if radius == 0 {
+            return;
+        }
+        for bit in from_bit..16 {
+            flip(value ^ (1 << bit), bit + 1, radius - 1, out);
+        }
+    }
+
+    let mut neighbors = Vec::new();
+    flip(chunk, 0, radius, &mut neighbors);
+    neighbors
+}
+
+/// Chunk search radius needed to find every hash within `distance`
+#[must_use]
+pub fn chunk_radius(distance: u32) -> u32 {
+    distance.min(MAX_DISTANCE) / CHUNKS
+}
+
+#[test]
+fn test_chunk_neighbors() {
+    assert_eq!(chunk_neighbors(0b1010, 0), vec![0b1010]);
+    let neighbors = chunk_neighbors(0, 2);
+    assert_eq!(neighbors.len(), 1 + 16 + 120);
+    assert!(neighbors.iter().all(|n| n.count_ones() <= 2));
+
+    let hash = 0x0123_4567_89ab_cdef;
+    assert_eq!(chunks(hash), [0x0123, 0x4567, 0x89ab, 0xcdef]);
+}
+
+#[test]
+fn test_dhash_resized() {
+    let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(120, 90, |x, y| {
+        image::Luma([u8::try_from((x / 20 * 37 + y / 15 * 53) % 256).unwrap()])
+    }));
+    let resized = image.resize_exact(60, 45, FilterType::Nearest);
+
+    assert!(distance(dhash(&image), dhash(&resized)) <= 3);
+}
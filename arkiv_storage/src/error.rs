@@ -0,0 +1,35 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// The requested file does not exist in the storage
+    NotFound,
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound => write!(f, "file not found"),
+            StorageError::Other(err) => write!(f, "storage error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            StorageError::NotFound
+        } else {
+            StorageError::Other(err.into())
+        }
+    }
+}
+
+impl From<anyhow::Error> for StorageError {
+    fn from(err: anyhow::Error) -> Self {
+        StorageError::Other(err)
+    }
+}
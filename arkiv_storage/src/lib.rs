@@ -1,18 +1,56 @@
+pub mod error;
 pub mod local;
 
-use std::path::PathBuf;
+pub use error::StorageError;
+
+use std::{ops::Range, path::PathBuf, time::SystemTime};
+
+use tokio::io::AsyncRead;
 
 #[macro_use]
 extern crate async_trait;
 
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    /// Size of the stored file in bytes
+    pub len: u64,
+
+    /// Time the file was last written, if the backend keeps track of it
+    pub modified: Option<SystemTime>,
+}
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
 #[async_trait]
 pub trait Storage
 where
     Self: Clone + Send + Sync + 'static,
 {
-    async fn save(&self, key: &str, subdir: Option<&str>, body: &[u8]) -> anyhow::Result<()>;
-    async fn get(&self, key: &str, subdir: Option<&str>) -> anyhow::Result<Vec<u8>>;
-    async fn exists(&self, key: &str, subdir: Option<&str>) -> anyhow::Result<bool>;
+    async fn save(&self, key: &str, subdir: Option<&str>, body: &[u8]) -> Result<()>;
+    async fn get(&self, key: &str, subdir: Option<&str>) -> Result<Vec<u8>>;
+    async fn exists(&self, key: &str, subdir: Option<&str>) -> Result<bool>;
+    async fn metadata(&self, key: &str, subdir: Option<&str>) -> Result<Metadata>;
+
+    /// Removes the file. Fails with [`StorageError::NotFound`] if it doesn't exist.
+    async fn delete(&self, key: &str, subdir: Option<&str>) -> Result<()>;
+
+    /// Reads the bytes in `range` of the file as they're consumed. The range has to lie
+    /// within the file.
+    async fn get_range(
+        &self,
+        key: &str,
+        subdir: Option<&str>,
+        range: Range<u64>,
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let body = self.get(key, subdir).await?;
+        let start = usize::try_from(range.start).map_err(anyhow::Error::from)?;
+        let end = usize::try_from(range.end).map_err(anyhow::Error::from)?;
+        let body = body
+            .get(start..end)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| anyhow::anyhow!("range {:?} out of bounds", range))?;
+        Ok(Box::new(std::io::Cursor::new(body)))
+    }
 }
 
 #[must_use]
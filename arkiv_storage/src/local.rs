@@ -1,9 +1,9 @@
-use std::path::PathBuf;
+use std::{ops::Range, path::PathBuf};
 
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tracing::{debug, debug_span};
 
-use crate::{split_path, Storage};
+use crate::{split_path, Metadata, Storage};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone)]
@@ -20,7 +20,7 @@ impl LocalStorage {
 
 #[async_trait]
 impl Storage for LocalStorage {
-    async fn save(&self, key: &str, subdir: Option<&str>, body: &[u8]) -> anyhow::Result<()> {
+    async fn save(&self, key: &str, subdir: Option<&str>, body: &[u8]) -> crate::Result<()> {
         let _span = debug_span!("localstorage");
 
         debug!("saving file {:?}", (&key, &subdir));
@@ -40,7 +40,7 @@ impl Storage for LocalStorage {
 
         Ok(())
     }
-    async fn get(&self, key: &str, subdir: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    async fn get(&self, key: &str, subdir: Option<&str>) -> crate::Result<Vec<u8>> {
         let subdirs = split_path(key);
 
         let mut root_path = self.path.clone();
@@ -54,7 +54,7 @@ impl Storage for LocalStorage {
 
         Ok(body)
     }
-    async fn exists(&self, key: &str, subdir: Option<&str>) -> anyhow::Result<bool> {
+    async fn exists(&self, key: &str, subdir: Option<&str>) -> crate::Result<bool> {
         let subdirs = split_path(key);
 
         let mut root_path = self.path.clone();
@@ -66,4 +66,58 @@ impl Storage for LocalStorage {
 
         Ok(root_path.exists())
     }
+    async fn metadata(&self, key: &str, subdir: Option<&str>) -> crate::Result<Metadata> {
+        let subdirs = split_path(key);
+
+        let mut root_path = self.path.clone();
+        if let Some(subdir) = subdir {
+            root_path.push(subdir);
+        }
+        root_path.push(subdirs);
+        root_path.push(key);
+
+        let metadata = tokio::fs::metadata(root_path).await?;
+
+        Ok(Metadata {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+    async fn delete(&self, key: &str, subdir: Option<&str>) -> crate::Result<()> {
+        let _span = debug_span!("localstorage");
+
+        let subdirs = split_path(key);
+
+        let mut root_path = self.path.clone();
+        if let Some(subdir) = subdir {
+            root_path.push(subdir);
+        }
+        root_path.push(subdirs);
+        root_path.push(key);
+
+        tokio::fs::remove_file(root_path).await?;
+        debug!("deleted file {:?}", (&key, &subdir));
+
+        Ok(())
+    }
+    async fn get_range(
+        &self,
+        key: &str,
+        subdir: Option<&str>,
+        range: Range<u64>,
+    ) -> crate::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let subdirs = split_path(key);
+
+        let mut root_path = self.path.clone();
+        if let Some(subdir) = subdir {
+            root_path.push(subdir);
+        }
+        root_path.push(subdirs);
+        root_path.push(key);
+
+        let mut file = tokio::fs::File::open(root_path).await?;
+        file.seek(std::io::SeekFrom::Start(range.start)).await?;
+
+        Ok(Box::new(file.take(range.end - range.start)))
+    }
 }
@@ -7,7 +7,7 @@ use tokio::sync::RwLock;
 
 use crate::{
     board::BoardsResponse,
-    thread::{ThreadPageListResponse, ThreadResponse, ThreadResponseInner},
+    thread::{CatalogResponse, ThreadPageListResponse, ThreadResponse, ThreadResponseInner},
 };
 
 #[derive(Debug, Default, Clone)]
@@ -36,6 +36,11 @@ impl Client {
         let req = self.http_client.get(&uri).build()?;
         Ok(self.http_client.execute(req).await?.json().await?)
     }
+    pub async fn get_catalog(&self, board: &str) -> anyhow::Result<CatalogResponse> {
+        let uri = format!("https://a.4cdn.org/{board}/catalog.json", board = board);
+        let req = self.http_client.get(&uri).build()?;
+        Ok(self.http_client.execute(req).await?.json().await?)
+    }
     pub async fn get_thread(&self, board: &str, thread_no: i64) -> anyhow::Result<ThreadResponse> {
         let uri = format!(
             "https://a.4cdn.org/{board}/thread/{thread}.json",
@@ -82,11 +87,23 @@ impl Client {
     ) -> anyhow::Result<Bytes> {
         let uri = format!("https://i.4cdn.org/{}/{}{}", &board, tim, ext);
         let req = self.http_client.get(&uri).build()?;
-        Ok(self.http_client.execute(req).await?.bytes().await?)
+        Ok(self
+            .http_client
+            .execute(req)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?)
     }
     pub async fn get_thumbnail_body(&self, board: &str, tim: i64) -> anyhow::Result<Bytes> {
         let uri = format!("https://i.4cdn.org/{}/{}s.jpg", &board, tim);
         let req = self.http_client.get(&uri).build()?;
-        Ok(self.http_client.execute(req).await?.bytes().await?)
+        Ok(self
+            .http_client
+            .execute(req)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?)
     }
 }
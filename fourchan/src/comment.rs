@@ -0,0 +1,570 @@
+//! Parser for the HTML in [`Post::com`](crate::Post::com).
+//!
+//! 4chan only emits a small set of tags in comments. Everything the parser doesn't
+//! know is reduced to its text, so the resulting tree can be rendered without passing
+//! any of the original markup through.
+
+use std::fmt::Write;
+
+/// A parsed post comment
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Comment {
+    pub nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Node {
+    Text(String),
+    LineBreak,
+    /// Line starting with `>`
+    Greentext(Vec<Node>),
+    /// Reference to a post, `>>123` or `>>>/g/123`
+    Quote(QuoteLink),
+    /// Reference to a board, `>>>/g/`
+    BoardLink(String),
+    Spoiler(Vec<Node>),
+    /// Contents of `[code]` tags
+    Code(String),
+    Bold(Vec<Node>),
+    Italic(Vec<Node>),
+    Underline(Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuoteLink {
+    /// Board of the quoted post if it's on another board than the quoting post
+    pub board: Option<String>,
+
+    /// Thread of the quoted post. `None` if the post is in the quoting thread or the
+    /// thread isn't known because the quoted post was deleted.
+    pub thread: Option<i64>,
+
+    pub post: i64,
+
+    /// The quoted post didn't exist anymore when the comment was archived
+    pub dead: bool,
+}
+
+impl Comment {
+    #[must_use]
+    pub fn parse(html: &str) -> Self {
+        let mut stack = vec![Frame {
+            element: Element::Root,
+            children: Vec::new(),
+        }];
+
+        for token in Tokenizer::new(html) {
+            match token {
+                Token::Text(text) => push_text(&mut stack, &decode_entities(text)),
+                Token::Open { name, class, href } => {
+                    let element = match (name.as_str(), class.as_deref()) {
+                        ("br", _) => {
+                            push_node(&mut stack, Node::LineBreak);
+                            continue;
+                        }
+                        ("wbr" | "img" | "hr", _) => continue,
+                        ("span", Some("quote")) => Element::Greentext,
+                        ("span", Some("deadlink")) => Element::DeadLink,
+                        ("a", _) => Element::Link(href),
+                        ("s", _) => Element::Spoiler,
+                        ("pre", _) => Element::Code,
+                        ("b" | "strong", _) => Element::Bold,
+                        ("i" | "em", _) => Element::Italic,
+                        ("u", _) => Element::Underline,
+                        _ => Element::Other(name),
+                    };
+                    stack.push(Frame {
+                        element,
+                        children: Vec::new(),
+                    });
+                }
+                Token::Close { name } => {
+                    // ignore closing tags without a matching opening tag
+                    if let Some(depth) = stack.iter().rposition(|f| f.element.closes(&name)) {
+                        while stack.len() > depth {
+                            close_frame(&mut stack);
+                        }
+                    }
+                }
+            }
+        }
+        while stack.len() > 1 {
+            close_frame(&mut stack);
+        }
+
+        Comment {
+            nodes: stack.pop().map(|f| f.children).unwrap_or_default(),
+        }
+    }
+
+    /// Text of the comment without markup. Quotes are written as `>>123`, line breaks as `\n`.
+    #[must_use]
+    pub fn plain_text(&self) -> String {
+        let mut text = String::new();
+        write_text(&mut text, &self.nodes);
+        text
+    }
+
+    /// All post references in the comment
+    #[must_use]
+    pub fn quotes(&self) -> Vec<&QuoteLink> {
+        fn collect<'a>(nodes: &'a [Node], quotes: &mut Vec<&'a QuoteLink>) {
+            for node in nodes {
+                match node {
+                    Node::Quote(quote) => quotes.push(quote),
+                    Node::Greentext(children)
+                    | Node::Spoiler(children)
+                    | Node::Bold(children)
+                    | Node::Italic(children)
+                    | Node::Underline(children) => collect(children, quotes),
+                    Node::Text(_) | Node::LineBreak | Node::BoardLink(_) | Node::Code(_) => {}
+                }
+            }
+        }
+
+        let mut quotes = Vec::new();
+        collect(&self.nodes, &mut quotes);
+        quotes
+    }
+}
+
+fn write_text(text: &mut String, nodes: &[Node]) {
+    for node in nodes {
+        match node {
+            Node::Text(t) | Node::Code(t) => text.push_str(t),
+            Node::LineBreak => text.push('\n'),
+            Node::Quote(quote) => {
+                let _ = match &quote.board {
+                    Some(board) => write!(text, ">>>/{}/{}", board, quote.post),
+                    None => write!(text, ">>{}", quote.post),
+                };
+            }
+            Node::BoardLink(board) => {
+                let _ = write!(text, ">>>/{}/", board);
+            }
+            Node::Greentext(children)
+            | Node::Spoiler(children)
+            | Node::Bold(children)
+            | Node::Italic(children)
+            | Node::Underline(children) => write_text(text, children),
+        }
+    }
+}
+
+enum Element {
+    Root,
+    Greentext,
+    DeadLink,
+    Link(Option<String>),
+    Spoiler,
+    Code,
+    Bold,
+    Italic,
+    Underline,
+    Other(String),
+}
+
+impl Element {
+    fn closes(&self, name: &str) -> bool {
+        match self {
+            Element::Root => false,
+            Element::Greentext | Element::DeadLink => name == "span",
+            Element::Link(_) => name == "a",
+            Element::Spoiler => name == "s",
+            Element::Code => name == "pre",
+            Element::Bold => name == "b" || name == "strong",
+            Element::Italic => name == "i" || name == "em",
+            Element::Underline => name == "u",
+            Element::Other(other) => other == name,
+        }
+    }
+}
+
+struct Frame {
+    element: Element,
+    children: Vec<Node>,
+}
+
+fn push_node(stack: &mut [Frame], node: Node) {
+    if let Some(frame) = stack.last_mut() {
+        frame.children.push(node);
+    }
+}
+
+fn push_text(stack: &mut [Frame], text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(frame) = stack.last_mut() {
+        if let Some(Node::Text(last)) = frame.children.last_mut() {
+            last.push_str(text);
+        } else {
+            frame.children.push(Node::Text(text.to_string()));
+        }
+    }
+}
+
+fn close_frame(stack: &mut Vec<Frame>) {
+    let frame = match stack.pop() {
+        Some(frame) => frame,
+        None => return,
+    };
+    let children = frame.children;
+
+    let node = match frame.element {
+        Element::Root => return,
+        Element::Greentext => Node::Greentext(children),
+        Element::Spoiler => Node::Spoiler(children),
+        Element::Bold => Node::Bold(children),
+        Element::Italic => Node::Italic(children),
+        Element::Underline => Node::Underline(children),
+        Element::Code => Node::Code(Comment { nodes: children }.plain_text()),
+        Element::DeadLink => {
+            let text = Comment { nodes: children }.plain_text();
+            match parse_quote_text(&text) {
+                Some(quote) => Node::Quote(quote),
+                None => Node::Text(text),
+            }
+        }
+        Element::Link(href) => match href.as_deref().and_then(parse_href) {
+            Some(node) => node,
+            None => {
+                for child in children {
+                    reinsert(stack, child);
+                }
+                return;
+            }
+        },
+        Element::Other(_) => {
+            for child in children {
+                reinsert(stack, child);
+            }
+            return;
+        }
+    };
+    reinsert(stack, node);
+}
+
+fn reinsert(stack: &mut [Frame], node: Node) {
+    match node {
+        Node::Text(text) => push_text(stack, &text),
+        node => push_node(stack, node),
+    }
+}
+
+/// Hosts of links to other boards
+const BOARD_HOSTS: [&str; 2] = ["boards.4chan.org", "boards.4channel.org"];
+
+/// Parses the target of a quotelink, e.g. `#p123`, `/g/thread/1#p2`,
+/// `//boards.4chan.org/g/thread/1#p2` or `//boards.4chan.org/g/catalog#s=rust`.
+/// Returns `None` for links that don't point into 4chan, e.g. to other sites.
+fn parse_href(href: &str) -> Option<Node> {
+    if let Some(post) = href.strip_prefix("#p") {
+        return Some(Node::Quote(QuoteLink {
+            board: None,
+            thread: None,
+            post: post.parse().ok()?,
+            dead: false,
+        }));
+    }
+
+    let path = href
+        .strip_prefix("https:")
+        .or_else(|| href.strip_prefix("http:"))
+        .unwrap_or(href);
+    // links to other boards include the host, links within the board don't
+    let (path, cross_board) = match path.strip_prefix("//") {
+        Some(rest) => {
+            let (host, path) = rest.split_at(rest.find('/')?);
+            if !BOARD_HOSTS.contains(&host) {
+                return None;
+            }
+            (path, true)
+        }
+        None => (path.strip_prefix('/')?, false),
+    };
+    let (path, fragment) = match path.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (path, None),
+    };
+
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let board = segments.next()?;
+    if !board.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    match (segments.next(), segments.next()) {
+        (Some("thread" | "res"), Some(thread)) => {
+            let thread: i64 = thread.trim_end_matches(".html").parse().ok()?;
+            let post = fragment
+                .and_then(|f| f.strip_prefix('p'))
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(thread);
+            Some(Node::Quote(QuoteLink {
+                board: cross_board.then_some(board.to_string()),
+                thread: Some(thread),
+                post,
+                dead: false,
+            }))
+        }
+        _ if cross_board => Some(Node::BoardLink(board.to_string())),
+        _ => None,
+    }
+}
+
+/// Parses the text of a dead link, `>>123` or `>>>/g/123`
+fn parse_quote_text(text: &str) -> Option<QuoteLink> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix(">>>/") {
+        let (board, post) = rest.split_once('/')?;
+        return Some(QuoteLink {
+            board: Some(board.to_string()),
+            thread: None,
+            post: post.trim_end_matches('/').parse().ok()?,
+            dead: true,
+        });
+    }
+
+    Some(QuoteLink {
+        board: None,
+        thread: None,
+        post: text.strip_prefix(">>")?.parse().ok()?,
+        dead: true,
+    })
+}
+
+/// Decodes the HTML entities 4chan uses in comments
+#[must_use]
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "gt" => Some('>'),
+        "lt" => Some('<'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix('x').or_else(|| code.strip_prefix('X')) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Open {
+        name: String,
+        class: Option<String>,
+        href: Option<String>,
+    },
+    Close {
+        name: String,
+    },
+}
+
+struct Tokenizer<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(html: &'a str) -> Self {
+        Tokenizer { rest: html }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        if !self.rest.starts_with('<') {
+            let end = self.rest.find('<').unwrap_or(self.rest.len());
+            let (text, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            return Some(Token::Text(text));
+        }
+
+        let end = match find_tag_end(self.rest) {
+            Some(end) => end,
+            None => {
+                // unterminated tag, treat the rest as text
+                let text = self.rest;
+                self.rest = "";
+                return Some(Token::Text(text));
+            }
+        };
+        let tag = &self.rest[1..end];
+        self.rest = &self.rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            return Some(Token::Close {
+                name: name.trim().to_ascii_lowercase(),
+            });
+        }
+
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        let attributes = parse_attributes(&tag[name_end..]);
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| decode_entities(v))
+        };
+
+        Some(Token::Open {
+            class: attribute("class"),
+            href: attribute("href"),
+            name,
+        })
+    }
+}
+
+/// Finds the `>` that ends the tag at the start of `html`, skipping quoted attribute values
+fn find_tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_attributes(mut attrs: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    loop {
+        attrs = attrs.trim_start();
+        if attrs.is_empty() {
+            break;
+        }
+        let key_end = attrs
+            .find(|c: char| c == '=' || c.is_ascii_whitespace())
+            .unwrap_or(attrs.len());
+        let key = &attrs[..key_end];
+        attrs = attrs[key_end..].trim_start();
+
+        let value = if let Some(rest) = attrs.strip_prefix('=') {
+            let rest = rest.trim_start();
+            let (value, rest) = match rest.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let end = rest[1..].find(q).map_or(rest.len(), |end| end + 1);
+                    (&rest[1..end], rest.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+            attrs = rest;
+            value
+        } else {
+            ""
+        };
+
+        if key.is_empty() {
+            // stray `=`, skip a character to make progress
+            attrs = attrs.get(1..).unwrap_or_default();
+        } else {
+            attributes.push((key, value));
+        }
+    }
+    attributes
+}
+
+#[test]
+fn test_parse_comment() {
+    let comment = Comment::parse(
+        r##"<a href="#p123" class="quotelink">&gt;&gt;123</a><br><span class="quote">&gt;implying</span><br>text &amp; <s>spoiler</s><wbr>"##,
+    );
+    assert_eq!(
+        comment.nodes,
+        vec![
+            Node::Quote(QuoteLink {
+                board: None,
+                thread: None,
+                post: 123,
+                dead: false
+            }),
+            Node::LineBreak,
+            Node::Greentext(vec![Node::Text(">implying".to_string())]),
+            Node::LineBreak,
+            Node::Text("text & ".to_string()),
+            Node::Spoiler(vec![Node::Text("spoiler".to_string())]),
+        ]
+    );
+    assert_eq!(comment.plain_text(), ">>123\n>implying\ntext & spoiler");
+}
+
+#[test]
+fn test_parse_links() {
+    let comment = Comment::parse(
+        r##"<a href="/g/thread/100#p101" class="quotelink">&gt;&gt;101</a> <a href="//boards.4chan.org/v/thread/5#p6" class="quotelink">&gt;&gt;&gt;/v/6</a> <a href="//boards.4chan.org/a/" class="quotelink">&gt;&gt;&gt;/a/</a> <a href="https://example.com/g/thread/1">example.com</a> <span class="deadlink">&gt;&gt;&gt;/b/7</span><pre class="prettyprint">fn main() {<br>}</pre><script>alert(1)</script>"##,
+    );
+    let quote = |board: Option<&str>, thread, post, dead| {
+        Node::Quote(QuoteLink {
+            board: board.map(str::to_string),
+            thread,
+            post,
+            dead,
+        })
+    };
+    assert_eq!(
+        comment.nodes,
+        vec![
+            quote(None, Some(100), 101, false),
+            Node::Text(" ".to_string()),
+            quote(Some("v"), Some(5), 6, false),
+            Node::Text(" ".to_string()),
+            Node::BoardLink("a".to_string()),
+            Node::Text(" example.com ".to_string()),
+            quote(Some("b"), None, 7, true),
+            Node::Code("fn main() {\n}".to_string()),
+            Node::Text("alert(1)".to_string()),
+        ]
+    );
+}
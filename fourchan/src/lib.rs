@@ -4,6 +4,7 @@
 
 pub mod board;
 pub mod client;
+pub mod comment;
 pub mod post;
 pub mod thread;
 
@@ -14,5 +15,6 @@ extern crate sqlx;
 
 pub use board::{Board, BoardsResponse};
 pub use client::Client;
+pub use comment::Comment;
 pub use post::{Post, PostAttachment};
-pub use thread::{ThreadPageListResponse, ThreadResponse};
+pub use thread::{CatalogResponse, ThreadPageListResponse, ThreadResponse};
@@ -369,6 +369,14 @@ pub struct Post {
 
     #[serde(skip_deserializing)]
     pub board: String,
+
+    /// Set by the archive's admins to keep the post off the public pages
+    #[serde(skip_deserializing)]
+    pub hidden: i64,
+
+    /// Set when the archive's admins removed the media, unlike `filedeleted` which 4chan sets
+    #[serde(skip_deserializing)]
+    pub media_removed: i64,
 }
 
 impl Post {
@@ -27,3 +27,13 @@ pub struct ThreadListPage {
 
 #[derive(Debug, Deserialize)]
 pub struct ThreadPageListResponse(pub Vec<ThreadListPage>);
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogPage {
+    pub page: i64,
+    /// Opening posts of the threads on the page
+    pub threads: Vec<Post>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogResponse(pub Vec<CatalogPage>);
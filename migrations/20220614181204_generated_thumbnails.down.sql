@@ -0,0 +1 @@
+DROP TABLE generated_thumbnails;
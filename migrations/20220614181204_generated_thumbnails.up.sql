@@ -0,0 +1,7 @@
+CREATE TABLE generated_thumbnails (
+    board           TEXT NOT NULL,
+    tim             INTEGER NOT NULL,
+    tn_w            INTEGER NOT NULL,
+    tn_h            INTEGER NOT NULL,
+    PRIMARY KEY (board, tim)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE skipped_media (
+    board           TEXT NOT NULL,
+    no              INTEGER NOT NULL,
+    tim             INTEGER NOT NULL,
+    ext             TEXT NOT NULL,
+    reason          TEXT NOT NULL,
+    skipped_at      INTEGER NOT NULL,
+    PRIMARY KEY (board, tim)
+);
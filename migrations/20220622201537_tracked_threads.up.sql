@@ -0,0 +1,10 @@
+CREATE TABLE tracked_threads (
+    board           TEXT NOT NULL,
+    no              INTEGER NOT NULL,
+    action          TEXT NOT NULL,
+    rule            TEXT NULL,
+    pinned          INTEGER NOT NULL,
+    first_seen      INTEGER NOT NULL,
+    updated_at      INTEGER NOT NULL,
+    PRIMARY KEY (board, no)
+);
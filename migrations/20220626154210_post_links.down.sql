@@ -0,0 +1,2 @@
+DROP INDEX post_links_target;
+DROP TABLE post_links;
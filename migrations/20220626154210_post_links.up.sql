@@ -0,0 +1,11 @@
+CREATE TABLE post_links (
+    board           TEXT NOT NULL,
+    no              INTEGER NOT NULL,
+    thread          INTEGER NOT NULL,
+    target_board    TEXT NOT NULL,
+    target_thread   INTEGER NULL,
+    target_post     INTEGER NOT NULL,
+    PRIMARY KEY (board, no, target_board, target_post)
+);
+
+CREATE INDEX post_links_target ON post_links (target_board, target_post);
@@ -0,0 +1 @@
+CREATE INDEX posts_board_resto ON posts (board, resto);
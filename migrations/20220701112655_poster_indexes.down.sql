@@ -0,0 +1,2 @@
+DROP INDEX posts_trip;
+DROP INDEX posts_board_id;
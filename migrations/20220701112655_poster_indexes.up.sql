@@ -0,0 +1,2 @@
+CREATE INDEX posts_board_id ON posts (board, id);
+CREATE INDEX posts_trip ON posts (trip);
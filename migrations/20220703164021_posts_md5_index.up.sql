@@ -0,0 +1 @@
+CREATE INDEX posts_md5 ON posts (md5);
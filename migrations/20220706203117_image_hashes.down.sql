@@ -0,0 +1,2 @@
+DROP INDEX posts_board_tim;
+DROP TABLE image_hashes;
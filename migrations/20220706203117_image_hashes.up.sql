@@ -0,0 +1,16 @@
+CREATE TABLE image_hashes (
+    board           TEXT NOT NULL,
+    tim             INTEGER NOT NULL,
+    hash            INTEGER NOT NULL,
+    chunk0          INTEGER NOT NULL,
+    chunk1          INTEGER NOT NULL,
+    chunk2          INTEGER NOT NULL,
+    chunk3          INTEGER NOT NULL,
+    PRIMARY KEY (board, tim)
+);
+
+CREATE INDEX image_hashes_chunk0 ON image_hashes (chunk0);
+CREATE INDEX image_hashes_chunk1 ON image_hashes (chunk1);
+CREATE INDEX image_hashes_chunk2 ON image_hashes (chunk2);
+CREATE INDEX image_hashes_chunk3 ON image_hashes (chunk3);
+CREATE INDEX posts_board_tim ON posts (board, tim);
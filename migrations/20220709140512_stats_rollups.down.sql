@@ -0,0 +1,9 @@
+DROP TABLE stats_threads;
+DROP TABLE stats_media;
+DROP TABLE stats_trips;
+DROP TABLE stats_flags;
+DROP TABLE stats_countries;
+DROP TABLE stats_hourly;
+DROP TABLE stats_boards;
+DROP TRIGGER posts_stats_queue;
+DROP TABLE stats_queue;
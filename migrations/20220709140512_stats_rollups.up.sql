@@ -0,0 +1,72 @@
+-- posts that haven't been counted in the rollups yet
+CREATE TABLE stats_queue (
+    id              INTEGER PRIMARY KEY NOT NULL,
+    board           TEXT NOT NULL,
+    no              INTEGER NOT NULL
+);
+
+CREATE TRIGGER posts_stats_queue AFTER INSERT ON posts
+BEGIN
+    INSERT INTO stats_queue (board, no) VALUES (new.board, new.no);
+END;
+
+INSERT INTO stats_queue (board, no) SELECT board, no FROM posts ORDER BY no;
+
+CREATE TABLE stats_boards (
+    board           TEXT PRIMARY KEY NOT NULL,
+    posts           INTEGER NOT NULL,
+    min_no          INTEGER NOT NULL,
+    max_no          INTEGER NOT NULL,
+    refreshed_at    INTEGER NOT NULL
+);
+
+CREATE TABLE stats_hourly (
+    board           TEXT NOT NULL,
+    hour            INTEGER NOT NULL,
+    posts           INTEGER NOT NULL,
+    threads         INTEGER NOT NULL,
+    images          INTEGER NOT NULL,
+    PRIMARY KEY (board, hour)
+);
+
+CREATE TABLE stats_countries (
+    board           TEXT NOT NULL,
+    country         TEXT NOT NULL,
+    country_name    TEXT NULL,
+    posts           INTEGER NOT NULL,
+    PRIMARY KEY (board, country)
+);
+
+CREATE TABLE stats_flags (
+    board           TEXT NOT NULL,
+    flag            TEXT NOT NULL,
+    flag_name       TEXT NULL,
+    posts           INTEGER NOT NULL,
+    PRIMARY KEY (board, flag)
+);
+
+CREATE TABLE stats_trips (
+    board           TEXT NOT NULL,
+    trip            TEXT NOT NULL,
+    posts           INTEGER NOT NULL,
+    first_post      INTEGER NOT NULL,
+    last_post       INTEGER NOT NULL,
+    PRIMARY KEY (board, trip)
+);
+
+CREATE TABLE stats_media (
+    board           TEXT NOT NULL,
+    ext             TEXT NOT NULL,
+    files           INTEGER NOT NULL,
+    bytes           INTEGER NOT NULL,
+    PRIMARY KEY (board, ext)
+);
+
+CREATE TABLE stats_threads (
+    board           TEXT NOT NULL,
+    no              INTEGER NOT NULL,
+    created         INTEGER NOT NULL,
+    last_post       INTEGER NOT NULL,
+    posts           INTEGER NOT NULL,
+    PRIMARY KEY (board, no)
+);
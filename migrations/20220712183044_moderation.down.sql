@@ -0,0 +1,5 @@
+DROP TABLE md5_blocklist;
+DROP TABLE audit_log;
+DROP TABLE purged_posts;
+DROP TRIGGER posts_hidden_thread;
+ALTER TABLE posts DROP COLUMN hidden;
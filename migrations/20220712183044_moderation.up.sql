@@ -0,0 +1,33 @@
+ALTER TABLE posts ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0;
+
+-- replies archived after their thread was hidden are hidden as well
+CREATE TRIGGER posts_hidden_thread AFTER INSERT ON posts
+WHEN new.resto != 0
+    AND EXISTS (SELECT 1 FROM posts WHERE board = new.board AND no = new.resto AND hidden = 1)
+BEGIN
+    UPDATE posts SET hidden = 1 WHERE rowid = new.rowid;
+END;
+
+-- purged posts and threads are never archived again
+CREATE TABLE purged_posts (
+    board           TEXT NOT NULL,
+    no              INTEGER NOT NULL,
+    PRIMARY KEY (board, no)
+);
+
+CREATE TABLE audit_log (
+    id              INTEGER PRIMARY KEY,
+    time            INTEGER NOT NULL,
+    actor           TEXT NOT NULL,
+    action          TEXT NOT NULL,
+    board           TEXT NULL,
+    no              INTEGER NULL,
+    md5             TEXT NULL,
+    reason          TEXT NULL
+);
+
+CREATE TABLE md5_blocklist (
+    md5             TEXT NOT NULL PRIMARY KEY,
+    reason          TEXT NULL,
+    created_at      INTEGER NOT NULL
+);
@@ -0,0 +1,4 @@
+DROP TABLE board_visibility;
+DROP TABLE api_tokens;
+DROP TABLE sessions;
+DROP TABLE users;
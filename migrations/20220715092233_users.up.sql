@@ -0,0 +1,31 @@
+CREATE TABLE users (
+    id              INTEGER PRIMARY KEY,
+    username        TEXT NOT NULL UNIQUE,
+    password_hash   TEXT NOT NULL,
+    admin           INTEGER NOT NULL DEFAULT 0,
+    created_at      INTEGER NOT NULL
+);
+
+-- only hashes of the session and API tokens are stored
+CREATE TABLE sessions (
+    token_hash      TEXT NOT NULL PRIMARY KEY,
+    user_id         INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at      INTEGER NOT NULL,
+    expires_at      INTEGER NOT NULL
+);
+
+CREATE TABLE api_tokens (
+    id              INTEGER PRIMARY KEY,
+    token_hash      TEXT NOT NULL UNIQUE,
+    user_id         INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name            TEXT NOT NULL,
+    scopes          TEXT NOT NULL,
+    created_at      INTEGER NOT NULL,
+    expires_at      INTEGER NULL,
+    last_used_at    INTEGER NULL
+);
+
+CREATE TABLE board_visibility (
+    board           TEXT NOT NULL PRIMARY KEY,
+    visibility      TEXT NOT NULL CHECK (visibility IN ('public', 'private'))
+);
@@ -0,0 +1 @@
+DROP VIEW private_boards;
@@ -0,0 +1,4 @@
+-- the boards only signed in users and tokens can see. Listings across boards filter with
+-- `(? OR board NOT IN private_boards)`, bound to whether the viewer may read them
+CREATE VIEW private_boards AS
+SELECT board FROM board_visibility WHERE visibility = 'private';
@@ -0,0 +1,2 @@
+UPDATE posts SET filedeleted = 1 WHERE media_removed = 1;
+ALTER TABLE posts DROP COLUMN media_removed;
@@ -0,0 +1,4 @@
+-- media the archive's admins removed, kept apart from 4chan's own filedeleted
+ALTER TABLE posts ADD COLUMN media_removed INTEGER NOT NULL DEFAULT 0;
+
+UPDATE posts SET media_removed = 1 WHERE md5 IN (SELECT md5 FROM md5_blocklist);
@@ -0,0 +1,4 @@
+DROP TRIGGER posts_stats_rebuild_hidden;
+DROP TRIGGER posts_stats_rebuild_delete;
+DROP INDEX stats_queue_post;
+DROP TABLE stats_rebuild;
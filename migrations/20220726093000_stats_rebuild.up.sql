@@ -0,0 +1,23 @@
+-- boards whose rollups count posts that were deleted or hidden (or unhidden) since
+CREATE TABLE stats_rebuild (
+    board           TEXT PRIMARY KEY NOT NULL
+);
+
+CREATE INDEX stats_queue_post ON stats_queue (board, no);
+
+-- posts still in the queue aren't counted yet, so they don't need a rebuild
+CREATE TRIGGER posts_stats_rebuild_delete AFTER DELETE ON posts
+WHEN NOT EXISTS (SELECT 1 FROM stats_queue WHERE board = old.board AND no = old.no)
+BEGIN
+    INSERT OR IGNORE INTO stats_rebuild (board) VALUES (old.board);
+END;
+
+CREATE TRIGGER posts_stats_rebuild_hidden AFTER UPDATE OF hidden ON posts
+WHEN old.hidden != new.hidden
+    AND NOT EXISTS (SELECT 1 FROM stats_queue WHERE board = new.board AND no = new.no)
+BEGIN
+    INSERT OR IGNORE INTO stats_rebuild (board) VALUES (new.board);
+END;
+
+-- hidden posts were counted until now
+INSERT INTO stats_rebuild (board) SELECT DISTINCT board FROM posts WHERE hidden = 1;
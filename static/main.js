@@ -0,0 +1,129 @@
+// Shows a preview of the quoted post when hovering over a quotelink.
+// Posts that aren't on the current page are looked up on their thread page.
+(function () {
+    const pages = new Map();
+    let preview = null;
+
+    function fetchPage(url) {
+        if (!pages.has(url)) {
+            pages.set(url, fetch(url)
+                .then((res) => res.ok ? res.text() : Promise.reject(res.status))
+                .then((html) => new DOMParser().parseFromString(html, "text/html"))
+                .catch(() => {
+                    pages.delete(url);
+                    return null;
+                }));
+        }
+        return pages.get(url);
+    }
+
+    async function findPost(link) {
+        const url = new URL(link.href, window.location.href);
+        const id = "p" + link.dataset.post;
+        if (url.pathname === window.location.pathname) {
+            return document.getElementById(id);
+        }
+        const page = await fetchPage(url.pathname);
+        return page && page.getElementById(id);
+    }
+
+    function removePreview() {
+        if (preview) {
+            preview.remove();
+            preview = null;
+        }
+    }
+
+    document.addEventListener("mouseover", async (event) => {
+        const link = event.target.closest("a.quotelink[data-post]");
+        if (!link) {
+            return;
+        }
+        const post = await findPost(link);
+        if (!post || !link.matches(":hover")) {
+            return;
+        }
+
+        removePreview();
+        preview = post.cloneNode(true);
+        preview.removeAttribute("id");
+        preview.classList.add("preview");
+        const rect = link.getBoundingClientRect();
+        preview.style.left = (rect.left + window.scrollX) + "px";
+        preview.style.top = (rect.bottom + window.scrollY + 4) + "px";
+        document.body.appendChild(preview);
+    });
+
+    document.addEventListener("mouseout", (event) => {
+        if (event.target.closest("a.quotelink[data-post]")) {
+            removePreview();
+        }
+    });
+})();
+
+// Hides catalog threads that don't contain the filter text.
+(function () {
+    const filter = document.getElementById("catalog-filter");
+    if (!filter) {
+        return;
+    }
+
+    filter.addEventListener("input", () => {
+        const needle = filter.value.trim().toLowerCase();
+        for (const thread of document.querySelectorAll(".catalog__thread")) {
+            const text = thread.querySelector(".catalog__teaser").textContent.toLowerCase();
+            thread.hidden = needle !== "" && !text.includes(needle);
+        }
+    });
+})();
+
+// Uploads an image to the similar image search and lists the matches.
+(function () {
+    const form = document.getElementById("similar-form");
+    if (!form) {
+        return;
+    }
+    const status = document.getElementById("similar-status");
+    const results = document.getElementById("similar-results");
+
+    form.addEventListener("submit", async (event) => {
+        event.preventDefault();
+        const file = form.elements.image.files[0];
+        if (!file) {
+            return;
+        }
+
+        status.textContent = "Searching...";
+        results.replaceChildren();
+        const distance = encodeURIComponent(form.elements.distance.value);
+        const res = await fetch("/api/image/similar?distance=" + distance, {
+            method: "POST",
+            headers: { "Content-Type": file.type || "application/octet-stream" },
+            body: file,
+        });
+        if (!res.ok) {
+            status.textContent = res.status === 422
+                ? "The file isn't an image that can be searched for."
+                : "Search failed (" + res.status + ").";
+            return;
+        }
+
+        const matches = await res.json();
+        status.textContent = matches.length + " match" + (matches.length === 1 ? "" : "es");
+        for (const { distance, post } of matches) {
+            const thread = post.resto === 0 ? post.no : post.resto;
+            const link = document.createElement("a");
+            link.className = "catalog__thread";
+            link.href = `/${post.board}/thread/${thread}#p${post.no}`;
+
+            const img = document.createElement("img");
+            img.src = `/cdn/${post.board}/${post.tim}s.jpg`;
+            img.loading = "lazy";
+            const caption = document.createElement("div");
+            caption.textContent = `/${post.board}/${post.no} (distance ${distance})`;
+
+            link.append(img, caption);
+            results.append(link);
+        }
+    });
+})();
@@ -0,0 +1,64 @@
+//! Embeds the templates and static files into the binary, see `src/assets.rs`
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    fmt::Write as _,
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+};
+
+fn main() -> io::Result<()> {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let templates_dir = manifest_dir.join("templates");
+    let static_dir = manifest_dir.join("../static");
+    println!("cargo:rerun-if-changed={}", templates_dir.display());
+    println!("cargo:rerun-if-changed={}", static_dir.display());
+
+    let mut out = String::from("pub static TEMPLATES: &[(&str, &str)] = &[\n");
+    for (name, path) in files(&templates_dir)? {
+        writeln!(out, "    ({:?}, include_str!({:?})),", name, path).unwrap();
+    }
+    out.push_str("];\n\npub static STATIC_FILES: &[(&str, &[u8], &str)] = &[\n");
+    for (name, path) in files(&static_dir)? {
+        let mut hasher = DefaultHasher::new();
+        fs::read(&path)?.hash(&mut hasher);
+        writeln!(
+            out,
+            "    ({:?}, include_bytes!({:?}), \"\\\"{:016x}\\\"\"),",
+            name,
+            path,
+            hasher.finish()
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+
+    fs::write(
+        PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("assets.rs"),
+        out,
+    )
+}
+
+/// Every file below `dir` with its path relative to `dir`, sorted by name
+fn files(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &format!("{}/", name), files)?;
+            } else {
+                files.push((name, fs::canonicalize(entry.path())?));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(dir, "", &mut files)?;
+    files.sort();
+    Ok(files)
+}
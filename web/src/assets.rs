@@ -0,0 +1,267 @@
+//! Templates and static files.
+//!
+//! Both are compiled into the binary, so the server runs from any directory. Files in the
+//! configured override directories take the place of built in files with the same name,
+//! which is enough to change the look of the site without rebuilding it. In development
+//! mode everything is read from the source tree instead, and templates are reloaded when
+//! they change.
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, HeaderMap, HeaderValue, Request, StatusCode};
+use tera::Tera;
+use tracing::{error, info};
+
+use crate::{
+    config::Features,
+    util::{html_decode, render_comment},
+};
+
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+}
+
+const SOURCE_TEMPLATES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/templates");
+const SOURCE_STATIC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../static");
+
+/// Where templates and static files are looked up, in order of precedence
+#[derive(Debug, Clone)]
+pub struct Assets {
+    dirs: Vec<PathBuf>,
+    embedded: bool,
+}
+
+impl Assets {
+    /// The built in files, shadowed by the files in `override_dir`. In development mode the
+    /// source tree takes the place of the built in files.
+    #[must_use]
+    pub fn templates(override_dir: Option<&Path>, dev: bool) -> Self {
+        Self::new(override_dir, dev.then(|| Path::new(SOURCE_TEMPLATES)))
+    }
+
+    #[must_use]
+    pub fn static_files(override_dir: Option<&Path>, dev: bool) -> Self {
+        Self::new(override_dir, dev.then(|| Path::new(SOURCE_STATIC)))
+    }
+
+    fn new(override_dir: Option<&Path>, source_dir: Option<&Path>) -> Self {
+        Assets {
+            dirs: override_dir
+                .into_iter()
+                .chain(source_dir)
+                .map(Path::to_path_buf)
+                .collect(),
+            embedded: source_dir.is_none(),
+        }
+    }
+}
+
+/// Compiles the templates
+pub fn templates(assets: &Assets, features: Features) -> anyhow::Result<Tera> {
+    let mut sources = HashMap::new();
+    if assets.embedded {
+        for (name, source) in embedded::TEMPLATES {
+            sources.insert((*name).to_string(), (*source).to_string());
+        }
+    }
+    // later directories take lower precedence, so they are read first
+    for dir in assets.dirs.iter().rev() {
+        for (name, path) in files(dir)? {
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read template {}", path.display()))?;
+            sources.insert(name, source);
+        }
+    }
+
+    let mut t = Tera::default();
+    t.add_raw_templates(sources)
+        .context("failed to compile templates")?;
+    t.register_filter("html_decode", html_decode);
+    t.register_filter("render_comment", render_comment);
+    // {% if feature(name="search") %} hides links to turned off parts of the site
+    t.register_function("feature", move |args: &HashMap<String, tera::Value>| {
+        let name = args
+            .get("name")
+            .and_then(tera::Value::as_str)
+            .ok_or_else(|| tera::Error::msg("feature needs a name"))?;
+        features
+            .enabled(name)
+            .map(tera::Value::Bool)
+            .ok_or_else(|| tera::Error::msg(format!("unknown feature {}", name)))
+    });
+    Ok(t)
+}
+
+/// Every file below `dir` with its path relative to `dir`
+fn files(dir: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> anyhow::Result<()> {
+        for entry in
+            std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &format!("{}/", name), files)?;
+            } else {
+                files.push((name, entry.path()));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(dir, "", &mut files)?;
+    Ok(files)
+}
+
+/// The newest modification time of the files below `dirs`
+fn last_modified(dirs: &[PathBuf]) -> Option<SystemTime> {
+    dirs.iter()
+        .filter_map(|dir| files(dir).ok())
+        .flatten()
+        .filter_map(|(_, path)| path.metadata().and_then(|meta| meta.modified()).ok())
+        .max()
+}
+
+/// Templates that are compiled again whenever a file changes, for development
+#[derive(Clone)]
+pub struct ReloadingTemplates {
+    assets: Assets,
+    features: Features,
+    current: Arc<Mutex<(Option<SystemTime>, Tera)>>,
+}
+
+impl ReloadingTemplates {
+    #[must_use]
+    pub fn new(assets: Assets, features: Features, t: Tera) -> Self {
+        let modified = last_modified(&assets.dirs);
+        ReloadingTemplates {
+            assets,
+            features,
+            current: Arc::new(Mutex::new((modified, t))),
+        }
+    }
+
+    /// The current templates, compiled again if a file changed. Broken templates are
+    /// logged and the last working ones kept.
+    fn get(&self) -> Tera {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        let modified = last_modified(&self.assets.dirs);
+        if modified != current.0 {
+            current.0 = modified;
+            match templates(&self.assets, self.features) {
+                Ok(t) => {
+                    info!("templates reloaded");
+                    current.1 = t;
+                }
+                Err(err) => error!("failed to reload templates: {:#}", err),
+            }
+        }
+        current.1.clone()
+    }
+}
+
+/// Middleware handing the handlers freshly reloaded templates
+pub async fn reload_templates(mut req: Request<Body>, next: Next<Body>) -> Response {
+    if let Some(templates) = req.extensions().get::<ReloadingTemplates>().cloned() {
+        req.extensions_mut().insert(templates.get());
+    }
+
+    next.run(req).await
+}
+
+/// Serves static files from the override directories or the binary
+pub async fn get_static(
+    extract::Path(path): extract::Path<String>,
+    extract::Extension(assets): extract::Extension<Assets>,
+    headers: HeaderMap,
+) -> Response {
+    let path = path.trim_start_matches('/');
+    // only plain relative paths, nothing may escape the directories
+    if path.is_empty()
+        || !Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let content_type =
+        HeaderValue::from_str(mime_guess::from_path(path).first_or_octet_stream().as_ref())
+            .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+
+    for dir in &assets.dirs {
+        match tokio::fs::read(dir.join(path)).await {
+            Ok(content) => {
+                return (
+                    [
+                        (header::CONTENT_TYPE, content_type),
+                        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+                    ],
+                    content,
+                )
+                    .into_response();
+            }
+            // directories don't count as files, the next place may have one
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound || dir.join(path).is_dir() => {}
+            Err(err) => {
+                error!("failed to read static file {}: {}", path, err);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    let file = embedded::STATIC_FILES
+        .iter()
+        .find(|(name, _, _)| *name == path)
+        .filter(|_| assets.embedded);
+    let (content, etag) = match file {
+        Some((_, content, etag)) => (*content, HeaderValue::from_static(etag)),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    // the files only change with the binary, so clients revalidate with the etag
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+    ];
+    if headers.get(header::IF_NONE_MATCH) == Some(&etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (
+        cache_headers,
+        [(header::CONTENT_TYPE, content_type)],
+        content,
+    )
+        .into_response()
+}
+
+#[test]
+fn test_embedded_assets() {
+    let t = templates(&Assets::templates(None, false), Features::default()).unwrap();
+    assert!(t.get_template_names().any(|name| name == "base.html"));
+    assert!(embedded::STATIC_FILES
+        .iter()
+        .any(|(name, _, _)| *name == "main.js"));
+
+    let dir = std::env::temp_dir().join(format!("arkiv-templates-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("login.html"), "overridden").unwrap();
+    let t = templates(&Assets::templates(Some(&dir), false), Features::default()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        t.render("login.html", &tera::Context::new()).unwrap(),
+        "overridden"
+    );
+    assert!(t.get_template_names().any(|name| name == "base.html"));
+}
@@ -0,0 +1,362 @@
+//! Users, sessions and API tokens.
+//!
+//! Signing in is optional. Anonymous visitors see every board that isn't private, signed in
+//! users and tokens with the `read` scope see all of them. Admins and tokens with the
+//! `admin` scope may use the admin area.
+
+use std::{collections::HashMap, convert::Infallible, num::NonZeroU32};
+
+use anyhow::Context;
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{self, FromRequest, RequestParts},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use chrono::Utc;
+use http::{header, HeaderMap, Request, StatusCode};
+use ring::{digest, pbkdf2, rand::SecureRandom};
+use sqlx::SqlitePool;
+use tracing::error;
+
+use crate::config::Features;
+
+pub const SESSION_COOKIE: &str = "arkiv_session";
+
+/// How long a session lasts after signing in
+pub const SESSION_SECONDS: i64 = 60 * 60 * 24 * 30;
+
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// What an API token may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scope {
+    /// See private boards
+    Read,
+    /// Use the admin area. Only admins can create such tokens
+    Admin,
+}
+
+impl Scope {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+
+    #[must_use]
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "read" => Some(Scope::Read),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub admin: bool,
+}
+
+/// Who made a request, resolved by [`identify`]
+#[derive(Debug, Clone, Default)]
+pub struct Viewer {
+    pub user: Option<User>,
+    scopes: Vec<Scope>,
+}
+
+impl Viewer {
+    /// Whether private boards are visible. Queries across boards leave them out with
+    /// `(? OR board NOT IN private_boards)`, bound to this.
+    #[must_use]
+    pub fn can_read_private(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    #[must_use]
+    pub fn is_admin(&self) -> bool {
+        self.scopes.contains(&Scope::Admin)
+    }
+
+    fn session(user: User) -> Self {
+        let scopes = if user.admin {
+            vec![Scope::Read, Scope::Admin]
+        } else {
+            vec![Scope::Read]
+        };
+        Viewer {
+            user: Some(user),
+            scopes,
+        }
+    }
+
+    fn token(user: User, scopes: &str) -> Self {
+        let scopes = scopes
+            .split_whitespace()
+            .filter_map(Scope::parse)
+            // admin tokens of users that lost their admin rights only grant read access
+            .filter(|&scope| scope != Scope::Admin || user.admin)
+            .collect();
+        Viewer {
+            user: Some(user),
+            scopes,
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Viewer {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Ok(req
+            .extensions()
+            .get::<Viewer>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Middleware resolving the session cookie or bearer token of a request to a [`Viewer`]
+pub async fn identify(mut req: Request<Body>, next: Next<Body>) -> Response {
+    let viewer = match req.extensions().get::<SqlitePool>() {
+        Some(pool) => viewer(pool, req.headers()).await.unwrap_or_else(|err| {
+            error!("failed to identify viewer: {}", err);
+            Viewer::default()
+        }),
+        None => Viewer::default(),
+    };
+    req.extensions_mut().insert(viewer);
+
+    next.run(req).await
+}
+
+async fn viewer(pool: &SqlitePool, headers: &HeaderMap) -> sqlx::Result<Viewer> {
+    let now = Utc::now().timestamp();
+
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if let Some(token) = bearer {
+        let token_hash = hash_token(token.trim());
+        let token = query!(
+            r#"
+            SELECT api_tokens.id, api_tokens.scopes, users.id as user_id, users.username,
+                users.admin as "admin: bool"
+            FROM api_tokens JOIN users ON users.id = api_tokens.user_id
+            WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > ?)
+            "#,
+            token_hash,
+            now
+        )
+        .fetch_optional(pool)
+        .await?;
+        return match token {
+            Some(token) => {
+                query!(
+                    "UPDATE api_tokens SET last_used_at = ? WHERE id = ?",
+                    now,
+                    token.id
+                )
+                .execute(pool)
+                .await?;
+                let user = User {
+                    id: token.user_id,
+                    username: token.username,
+                    admin: token.admin,
+                };
+                Ok(Viewer::token(user, &token.scopes))
+            }
+            None => Ok(Viewer::default()),
+        };
+    }
+
+    if let Some(session) = session_token(headers) {
+        let token_hash = hash_token(session);
+        let user = query_as!(
+            User,
+            r#"
+            SELECT users.id, users.username, users.admin as "admin: bool"
+            FROM sessions JOIN users ON users.id = sessions.user_id
+            WHERE token_hash = ? AND expires_at > ?
+            "#,
+            token_hash,
+            now
+        )
+        .fetch_optional(pool)
+        .await?;
+        return Ok(user.map(Viewer::session).unwrap_or_default());
+    }
+
+    Ok(Viewer::default())
+}
+
+/// The token in the session cookie, if there is one
+#[must_use]
+pub fn session_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|cookies| cookie(cookies, SESSION_COOKIE))
+}
+
+/// Finds the value of a cookie in a `Cookie` header
+fn cookie<'a>(cookies: &'a str, name: &str) -> Option<&'a str> {
+    cookies.split(';').find_map(|cookie| {
+        let (key, value) = cookie.trim().split_once('=')?;
+        (key == name).then_some(value)
+    })
+}
+
+/// Middleware keeping private boards from anonymous visitors. Applies to every route with
+/// a `:board` parameter. Pages redirect to the login if accounts are turned on, everything
+/// else pretends the board doesn't exist.
+pub async fn board_visibility(req: Request<Body>, next: Next<Body>) -> Response {
+    let mut parts = RequestParts::new(req);
+    let board = extract::Path::<HashMap<String, String>>::from_request(&mut parts)
+        .await
+        .ok()
+        .and_then(|extract::Path(mut params)| params.remove("board"));
+    let req = match parts.try_into_request() {
+        Ok(req) => req,
+        Err(err) => return err.into_response(),
+    };
+
+    let viewer = req
+        .extensions()
+        .get::<Viewer>()
+        .cloned()
+        .unwrap_or_default();
+    if let (Some(board), Some(pool)) = (board, req.extensions().get::<SqlitePool>()) {
+        if !viewer.can_read_private() {
+            match is_private(pool, &board).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    let path = req.uri().path();
+                    let accounts = req
+                        .extensions()
+                        .get::<Features>()
+                        .map_or(false, |features| features.accounts);
+                    return if !accounts
+                        || ["/api/", "/cdn/", "/i/"]
+                            .iter()
+                            .any(|prefix| path.starts_with(prefix))
+                    {
+                        StatusCode::NOT_FOUND.into_response()
+                    } else {
+                        login_redirect(path).into_response()
+                    };
+                }
+                Err(err) => {
+                    error!("failed to check the visibility of /{}/: {}", board, err);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+        }
+    }
+
+    next.run(req).await
+}
+
+async fn is_private(pool: &SqlitePool, board: &str) -> sqlx::Result<bool> {
+    query_scalar!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM private_boards WHERE board = ?)
+            as "private!: bool"
+        "#,
+        board
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Sends the visitor to the login page, which returns them to `next` afterwards
+pub fn login_redirect(next: &str) -> Redirect {
+    let query = serde_urlencoded::to_string([("next", next)]).unwrap_or_default();
+    Redirect::to(&format!("/login?{}", query))
+}
+
+/// Hashes a password with PBKDF2 into `pbkdf2-sha256$iterations$salt$hash`
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let mut salt = [0; 16];
+    ring::rand::SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("failed to generate salt"))?;
+    let mut hash = [0; digest::SHA256_OUTPUT_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).context("invalid iteration count")?,
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+
+    Ok(format!(
+        "pbkdf2-sha256${}${}${}",
+        PBKDF2_ITERATIONS,
+        base64::encode(salt),
+        base64::encode(hash)
+    ))
+}
+
+/// Checks a password against a hash from [`hash_password`]
+#[must_use]
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let parts = password_hash.split('$').collect::<Vec<_>>();
+    let (iterations, salt, hash) = match parts.as_slice() {
+        ["pbkdf2-sha256", iterations, salt, hash] => (iterations, salt, hash),
+        _ => return false,
+    };
+    let iterations = match iterations.parse().ok().and_then(NonZeroU32::new) {
+        Some(iterations) => iterations,
+        None => return false,
+    };
+    match (base64::decode(salt), base64::decode(hash)) {
+        (Ok(salt), Ok(hash)) => pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &hash,
+        )
+        .is_ok(),
+        _ => false,
+    }
+}
+
+/// Creates a random token for a session or API access
+pub fn generate_token() -> anyhow::Result<String> {
+    let mut token = [0; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut token)
+        .map_err(|_| anyhow::anyhow!("failed to generate token"))?;
+    Ok(base64::encode_config(token, base64::URL_SAFE_NO_PAD))
+}
+
+/// Tokens are random, so a plain hash is enough to keep them out of the database
+#[must_use]
+pub fn hash_token(token: &str) -> String {
+    base64::encode(digest::digest(&digest::SHA256, token.as_bytes()))
+}
+
+#[test]
+fn test_passwords_and_cookies() {
+    let hash = hash_password("hunter2").unwrap();
+    assert!(verify_password("hunter2", &hash));
+    assert!(!verify_password("hunter3", &hash));
+    assert!(!verify_password("hunter2", "hunter2"));
+
+    assert_eq!(
+        cookie("theme=dark; arkiv_session=abc=; x=1", SESSION_COOKIE),
+        Some("abc=")
+    );
+    assert_eq!(cookie("arkiv_session_old=abc", SESSION_COOKIE), None);
+}
@@ -0,0 +1,319 @@
+//! Commands for managing users, API tokens and board visibility
+
+use std::io::BufRead;
+
+use anyhow::Context;
+use chrono::{TimeZone, Utc};
+use clap::{Args, Subcommand, ValueEnum};
+use sqlx::SqlitePool;
+
+use crate::auth::{self, Scope};
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// Create a user. The password is read from stdin
+    Add {
+        username: String,
+        /// Allow the user to use the admin area
+        #[clap(long)]
+        admin: bool,
+    },
+    /// Set a new password, read from stdin. Signs the user out everywhere
+    Passwd {
+        username: String,
+    },
+    /// Grant or revoke admin rights
+    Admin {
+        username: String,
+        #[clap(action = clap::ArgAction::Set)]
+        admin: bool,
+    },
+    /// Delete a user together with their sessions and tokens
+    Remove {
+        username: String,
+    },
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TokenCommand {
+    /// Create an API token for a user. The token is only shown once
+    Create {
+        username: String,
+        /// What the token is used for
+        #[clap(long)]
+        name: String,
+        /// Can be given multiple times
+        #[clap(long = "scope", value_enum, required = true)]
+        scopes: Vec<Scope>,
+        /// Let the token expire after this many days
+        #[clap(long)]
+        expires_in_days: Option<i64>,
+    },
+    /// List the tokens of all users or of one
+    List {
+        username: Option<String>,
+    },
+    Revoke {
+        id: i64,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct VisibilityArgs {
+    board: String,
+    /// Leave out to show the current visibility
+    #[clap(value_enum)]
+    visibility: Option<Visibility>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Visibility {
+    /// Everyone can see the board
+    Public,
+    /// Only signed in users and tokens with the read scope can see the board
+    Private,
+}
+
+impl Visibility {
+    fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+pub async fn user(pool: &SqlitePool, command: UserCommand) -> anyhow::Result<()> {
+    match command {
+        UserCommand::Add { username, admin } => {
+            let password_hash = auth::hash_password(&read_password()?)?;
+            let created_at = Utc::now().timestamp();
+            query!(
+                r#"
+                INSERT INTO users (username, password_hash, admin, created_at)
+                VALUES (?, ?, ?, ?)
+                "#,
+                username,
+                password_hash,
+                admin,
+                created_at
+            )
+            .execute(pool)
+            .await
+            .with_context(|| format!("failed to create user {}", username))?;
+            println!("created user {}", username);
+        }
+        UserCommand::Passwd { username } => {
+            let password_hash = auth::hash_password(&read_password()?)?;
+            let mut tx = pool.begin().await?;
+            let id = user_id(&mut tx, &username).await?;
+            query!(
+                "UPDATE users SET password_hash = ? WHERE id = ?",
+                password_hash,
+                id
+            )
+            .execute(&mut tx)
+            .await?;
+            query!("DELETE FROM sessions WHERE user_id = ?", id)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+            println!("changed the password of {}", username);
+        }
+        UserCommand::Admin { username, admin } => {
+            let mut tx = pool.begin().await?;
+            let id = user_id(&mut tx, &username).await?;
+            query!("UPDATE users SET admin = ? WHERE id = ?", admin, id)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+            println!(
+                "{} is {}an admin",
+                username,
+                if admin { "" } else { "no longer " }
+            );
+        }
+        UserCommand::Remove { username } => {
+            let mut tx = pool.begin().await?;
+            let id = user_id(&mut tx, &username).await?;
+            // sessions and tokens are deleted by the foreign keys
+            query!("DELETE FROM users WHERE id = ?", id)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+            println!("removed user {}", username);
+        }
+        UserCommand::List => {
+            let users = query!(
+                r#"SELECT username, admin as "admin: bool", created_at FROM users ORDER BY username"#
+            )
+            .fetch_all(pool)
+            .await?;
+            for user in users {
+                println!(
+                    "{}\t{}\tcreated {}",
+                    user.username,
+                    if user.admin { "admin" } else { "user" },
+                    date(user.created_at)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn token(pool: &SqlitePool, command: TokenCommand) -> anyhow::Result<()> {
+    match command {
+        TokenCommand::Create {
+            username,
+            name,
+            mut scopes,
+            expires_in_days,
+        } => {
+            let user = query!(
+                r#"SELECT id, admin as "admin: bool" FROM users WHERE username = ?"#,
+                username
+            )
+            .fetch_optional(pool)
+            .await?
+            .with_context(|| format!("there is no user {}", username))?;
+            if scopes.contains(&Scope::Admin) && !user.admin {
+                anyhow::bail!("{} is not an admin and can't have admin tokens", username);
+            }
+            scopes.sort_by_key(|scope| scope.as_str());
+            scopes.dedup();
+            let scopes = scopes
+                .iter()
+                .map(|scope| scope.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let token = auth::generate_token()?;
+            let token_hash = auth::hash_token(&token);
+            let created_at = Utc::now().timestamp();
+            let expires_at = expires_in_days.map(|days| created_at + days * 60 * 60 * 24);
+            query!(
+                r#"
+                INSERT INTO api_tokens (token_hash, user_id, name, scopes, created_at, expires_at)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                token_hash,
+                user.id,
+                name,
+                scopes,
+                created_at,
+                expires_at
+            )
+            .execute(pool)
+            .await?;
+            println!("{}", token);
+        }
+        TokenCommand::List { username } => {
+            let tokens = query!(
+                r#"
+                SELECT api_tokens.id, users.username, name, scopes, api_tokens.created_at,
+                    expires_at, last_used_at
+                FROM api_tokens JOIN users ON users.id = api_tokens.user_id
+                WHERE ? IS NULL OR users.username = ?
+                ORDER BY api_tokens.id
+                "#,
+                username,
+                username
+            )
+            .fetch_all(pool)
+            .await?;
+            for token in tokens {
+                println!(
+                    "{}\t{}\t{}\t[{}]\tcreated {}\texpires {}\tlast used {}",
+                    token.id,
+                    token.username,
+                    token.name,
+                    token.scopes,
+                    date(token.created_at),
+                    token.expires_at.map_or_else(|| "never".to_string(), date),
+                    token.last_used_at.map_or_else(|| "never".to_string(), date),
+                );
+            }
+        }
+        TokenCommand::Revoke { id } => {
+            let result = query!("DELETE FROM api_tokens WHERE id = ?", id)
+                .execute(pool)
+                .await?;
+            if result.rows_affected() == 0 {
+                anyhow::bail!("there is no token {}", id);
+            }
+            println!("revoked token {}", id);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn visibility(pool: &SqlitePool, args: VisibilityArgs) -> anyhow::Result<()> {
+    if let Some(visibility) = args.visibility {
+        let visibility = visibility.as_str();
+        query!(
+            r#"
+            INSERT INTO board_visibility (board, visibility) VALUES (?, ?)
+            ON CONFLICT(board) DO UPDATE SET visibility = excluded.visibility
+            "#,
+            args.board,
+            visibility
+        )
+        .execute(pool)
+        .await?;
+        println!("/{}/ is {}", args.board, visibility);
+    } else {
+        let visibility = query_scalar!(
+            "SELECT visibility FROM board_visibility WHERE board = ?",
+            args.board
+        )
+        .fetch_optional(pool)
+        .await?;
+        println!(
+            "/{}/ is {}",
+            args.board,
+            visibility.as_deref().unwrap_or("public")
+        );
+    }
+
+    Ok(())
+}
+
+async fn user_id(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    username: &str,
+) -> anyhow::Result<i64> {
+    query_scalar!(
+        r#"SELECT id as "id!: i64" FROM users WHERE username = ?"#,
+        username
+    )
+    .fetch_optional(tx)
+    .await?
+    .with_context(|| format!("there is no user {}", username))
+}
+
+/// Reads a password from the first line of stdin
+fn read_password() -> anyhow::Result<String> {
+    eprint!("Password: ");
+    let mut password = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .context("failed to read password")?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]);
+    if password.is_empty() {
+        anyhow::bail!("the password must not be empty");
+    }
+    Ok(password.to_string())
+}
+
+fn date(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map_or_else(String::new, |time| {
+            time.format("%Y-%m-%d %H:%M").to_string()
+        })
+}
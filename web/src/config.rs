@@ -0,0 +1,474 @@
+//! Configuration of the web server.
+//!
+//! Read from a YAML file, `web.yml` unless `--config` or `WEB_CONFIG_PATH` say otherwise.
+//! Every setting has a default, so the file may be missing. Environment variables override
+//! the file, which keeps the variables shared with the archiver, like `DATABASE_URL` and
+//! `DATA_DIR`, working.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+
+use crate::{
+    handler::AdminCredentials,
+    ratelimit::{Budget, IpNetwork, RateLimitConfig},
+};
+
+const DEFAULT_PATH: &str = "web.yml";
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Env: `DATABASE_URL`
+    pub database_url: Option<String>,
+
+    /// Addresses to listen on.
+    ///
+    /// Env: `LISTEN`, comma separated. Default: `["0.0.0.0:8080"]`
+    pub listen: Vec<SocketAddr>,
+
+    /// Unix socket to listen on, in addition to `listen`. An existing socket at the path
+    /// is replaced.
+    ///
+    /// Env: `UNIX_SOCKET`
+    pub unix_socket: Option<PathBuf>,
+
+    /// URL the site is reached at, like `https://archive.example.com`. Used for the absolute
+    /// links in feeds. Taken from the `Host` header of each request if unset.
+    ///
+    /// Env: `BASE_URL`
+    pub base_url: Option<String>,
+
+    /// Templates in this directory replace the built in templates with the same name.
+    ///
+    /// Env: `TEMPLATES_DIR`
+    pub templates_dir: Option<PathBuf>,
+
+    /// Files in this directory replace the built in static files with the same name.
+    ///
+    /// Env: `STATIC_DIR`
+    pub static_dir: Option<PathBuf>,
+
+    /// Serve templates and static files from the source tree instead of the binary and
+    /// reload templates when they change.
+    ///
+    /// Env: `DEV`. Default: `false`
+    pub dev: bool,
+
+    pub storage: StorageConfig,
+
+    pub pages: PageSizes,
+
+    pub features: Features,
+
+    /// HTTP basic auth credentials for the admin area, in addition to admin users.
+    ///
+    /// Env: `ADMIN_USERNAME` and `ADMIN_PASSWORD`
+    pub admin: Option<AdminCredentials>,
+
+    pub rate_limit: RateLimitSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database_url: None,
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 8080))],
+            unix_socket: None,
+            base_url: None,
+            templates_dir: None,
+            static_dir: None,
+            dev: false,
+            storage: StorageConfig::default(),
+            pages: PageSizes::default(),
+            features: Features::default(),
+            admin: None,
+            rate_limit: RateLimitSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "backend", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StorageConfig {
+    /// Files in a directory, as written by the archiver
+    Local {
+        /// Env: `DATA_DIR`
+        path: Option<PathBuf>,
+    },
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig::Local { path: None }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct PageSizes {
+    /// Env: `THREADS_PER_PAGE`. Default: `40`
+    pub threads_per_page: i64,
+
+    /// Threads shown in the catalog.
+    ///
+    /// Env: `CATALOG_THREADS`. Default: `150`
+    pub catalog_threads: i64,
+
+    /// Posts per page of search results, tripcode and image listings.
+    ///
+    /// Env: `RESULTS_PER_PAGE`. Default: `50`
+    pub results_per_page: i64,
+}
+
+impl Default for PageSizes {
+    fn default() -> Self {
+        PageSizes {
+            threads_per_page: 40,
+            catalog_threads: 150,
+            results_per_page: 50,
+        }
+    }
+}
+
+impl PageSizes {
+    /// Number of result pages for `total` posts, at least one
+    #[must_use]
+    pub fn result_pages(&self, total: i64) -> i64 {
+        ((total + self.results_per_page - 1) / self.results_per_page).max(1)
+    }
+}
+
+/// Parts of the site that can be turned off. Everything is on by default.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Features {
+    /// Full text search and tripcode listings
+    pub search: bool,
+    /// Searching by file name, hash and similar images
+    pub image_search: bool,
+    /// Atom feeds of boards, threads and searches
+    pub feeds: bool,
+    pub stats: bool,
+    /// JSON endpoints under `/api`
+    pub api: bool,
+    pub admin: bool,
+    /// Signing in with a username and password. API tokens keep working without it.
+    pub accounts: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            search: true,
+            image_search: true,
+            feeds: true,
+            stats: true,
+            api: true,
+            admin: true,
+            accounts: true,
+        }
+    }
+}
+
+impl Features {
+    /// Looks up a feature by its name in the config file
+    #[must_use]
+    pub fn enabled(self, name: &str) -> Option<bool> {
+        match name {
+            "search" => Some(self.search),
+            "image_search" => Some(self.image_search),
+            "feeds" => Some(self.feeds),
+            "stats" => Some(self.stats),
+            "api" => Some(self.api),
+            "admin" => Some(self.admin),
+            "accounts" => Some(self.accounts),
+            _ => None,
+        }
+    }
+}
+
+/// Budgets are written as `<requests>/<seconds>`, or `off`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    /// Env: `RATE_LIMIT_HTML`. Default: `120/60`
+    pub html: String,
+
+    /// Env: `RATE_LIMIT_API`. Default: `300/60`
+    pub api: String,
+
+    /// Env: `RATE_LIMIT_SEARCH`. Default: `20/60`
+    pub search: String,
+
+    /// Env: `RATE_LIMIT_MEDIA`. Default: `600/60`
+    pub media: String,
+
+    /// Reverse proxies whose `X-Forwarded-For` header is believed, as addresses or networks
+    /// like `10.0.0.0/8`.
+    ///
+    /// Env: `TRUSTED_PROXIES`, comma separated
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        RateLimitSettings {
+            html: "120/60".to_string(),
+            api: "300/60".to_string(),
+            search: "20/60".to_string(),
+            media: "600/60".to_string(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitSettings {
+    pub fn parse(&self) -> anyhow::Result<RateLimitConfig> {
+        fn budget(key: &str, value: &str) -> anyhow::Result<Option<Budget>> {
+            Budget::parse(value).with_context(|| format!("rate_limit.{}: {}", key, value))
+        }
+
+        Ok(RateLimitConfig {
+            html: budget("html", &self.html)?,
+            api: budget("api", &self.api)?,
+            search: budget("search", &self.search)?,
+            media: budget("media", &self.media)?,
+            trusted_proxies: self
+                .trusted_proxies
+                .iter()
+                .map(|proxy| {
+                    IpNetwork::parse(proxy)
+                        .with_context(|| format!("rate_limit.trusted_proxies: {}", proxy))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+}
+
+impl Config {
+    /// Loads the config file and applies the environment. `path` falls back to
+    /// `WEB_CONFIG_PATH` and then to `web.yml`, which may be missing.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let env_path = std::env::var_os("WEB_CONFIG_PATH").map(PathBuf::from);
+        let mut config = match path.or(env_path.as_deref()) {
+            Some(path) => Self::read(path)?,
+            None if Path::new(DEFAULT_PATH).exists() => Self::read(Path::new(DEFAULT_PATH))?,
+            None => Config::default(),
+        };
+        config.apply_env(|key| std::env::var(key).ok())?;
+
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let raw_config = std::fs::read(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        serde_yaml::from_slice(&raw_config)
+            .with_context(|| format!("failed to deserialize config file {}", path.display()))
+    }
+
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        fn parse<T: FromStr>(key: &str, value: &str) -> anyhow::Result<T>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            value
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid {}: {}", key, value))
+        }
+
+        fn list(value: &str) -> impl Iterator<Item = &str> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+        }
+
+        if let Some(value) = var("DATABASE_URL") {
+            self.database_url = Some(value);
+        }
+        if let Some(value) = var("LISTEN") {
+            self.listen = list(&value)
+                .map(|addr| parse("LISTEN", addr))
+                .collect::<anyhow::Result<_>>()?;
+        }
+        if let Some(value) = var("UNIX_SOCKET") {
+            self.unix_socket =
+                Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+        }
+        if let Some(value) = var("BASE_URL") {
+            self.base_url = Some(value);
+        }
+        if let Some(value) = var("TEMPLATES_DIR") {
+            self.templates_dir =
+                Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+        }
+        if let Some(value) = var("STATIC_DIR") {
+            self.static_dir =
+                Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+        }
+        if let Some(value) = var("DEV") {
+            self.dev = parse("DEV", &value)?;
+        }
+        if let Some(value) = var("DATA_DIR") {
+            self.storage = StorageConfig::Local {
+                path: Some(PathBuf::from(value)),
+            };
+        }
+
+        if let Some(value) = var("THREADS_PER_PAGE") {
+            self.pages.threads_per_page = parse("THREADS_PER_PAGE", &value)?;
+        }
+        if let Some(value) = var("CATALOG_THREADS") {
+            self.pages.catalog_threads = parse("CATALOG_THREADS", &value)?;
+        }
+        if let Some(value) = var("RESULTS_PER_PAGE") {
+            self.pages.results_per_page = parse("RESULTS_PER_PAGE", &value)?;
+        }
+
+        match (var("ADMIN_USERNAME"), var("ADMIN_PASSWORD")) {
+            // an empty password turns off the credentials from the file
+            (Some(_), Some(password)) if password.is_empty() => self.admin = None,
+            (Some(username), Some(password)) => {
+                self.admin = Some(AdminCredentials { username, password });
+            }
+            (None, None) => {}
+            _ => anyhow::bail!("ADMIN_USERNAME and ADMIN_PASSWORD have to be set together"),
+        }
+
+        for (key, budget) in [
+            ("RATE_LIMIT_HTML", &mut self.rate_limit.html),
+            ("RATE_LIMIT_API", &mut self.rate_limit.api),
+            ("RATE_LIMIT_SEARCH", &mut self.rate_limit.search),
+            ("RATE_LIMIT_MEDIA", &mut self.rate_limit.media),
+        ] {
+            if let Some(value) = var(key) {
+                *budget = value;
+            }
+        }
+        if let Some(value) = var("TRUSTED_PROXIES") {
+            self.rate_limit.trusted_proxies = list(&value).map(str::to_string).collect();
+        }
+
+        Ok(())
+    }
+
+    /// Checks everything that can be checked before serving, reporting all problems at once
+    pub fn validate(&mut self) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+
+        if self.database_url.is_none() {
+            errors.push("database_url is not set".to_string());
+        }
+        if self.listen.is_empty() && self.unix_socket.is_none() {
+            errors.push("there is nothing to listen on, set listen or unix_socket".to_string());
+        }
+        if let Some(base_url) = &mut self.base_url {
+            if base_url.starts_with("http://") || base_url.starts_with("https://") {
+                *base_url = base_url.trim_end_matches('/').to_string();
+            } else {
+                errors.push(format!(
+                    "base_url has to start with http:// or https://: {}",
+                    base_url
+                ));
+            }
+        }
+        for (key, dir) in [
+            ("templates_dir", &self.templates_dir),
+            ("static_dir", &self.static_dir),
+        ] {
+            if let Some(dir) = dir.as_ref().filter(|dir| !dir.is_dir()) {
+                errors.push(format!("{} is not a directory: {}", key, dir.display()));
+            }
+        }
+        match &self.storage {
+            StorageConfig::Local { path: None } => {
+                errors.push("storage.path is not set".to_string());
+            }
+            StorageConfig::Local { path: Some(path) } if !path.is_dir() => {
+                errors.push(format!(
+                    "storage.path is not a directory: {}",
+                    path.display()
+                ));
+            }
+            StorageConfig::Local { .. } => {}
+        }
+        for (key, size) in [
+            ("pages.threads_per_page", self.pages.threads_per_page),
+            ("pages.catalog_threads", self.pages.catalog_threads),
+            ("pages.results_per_page", self.pages.results_per_page),
+        ] {
+            if !(1..=1000).contains(&size) {
+                errors.push(format!("{} has to be between 1 and 1000: {}", key, size));
+            }
+        }
+        if let Some(admin) = &self.admin {
+            if admin.username.is_empty() || admin.password.is_empty() {
+                errors.push("admin.username and admin.password must not be empty".to_string());
+            }
+        }
+        if let Err(err) = self.rate_limit.parse() {
+            errors.push(format!("{:#}", err));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("invalid configuration:\n  {}", errors.join("\n  "))
+        }
+    }
+}
+
+#[test]
+fn test_config() {
+    let mut config: Config = serde_yaml::from_str(
+        r#"
+        listen: ["127.0.0.1:3000"]
+        base_url: https://example.com/
+        storage:
+          backend: local
+          path: .
+        pages:
+          results_per_page: 20
+        features:
+          stats: false
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.pages.threads_per_page, 40);
+    assert!(!config.features.stats);
+    assert_eq!(config.features.enabled("search"), Some(true));
+
+    let env = [
+        ("DATABASE_URL", "sqlite://test.sq3"),
+        ("LISTEN", "127.0.0.1:4000, [::1]:4000"),
+        ("RESULTS_PER_PAGE", "25"),
+        ("TEMPLATES_DIR", "."),
+        ("STATIC_DIR", "."),
+    ];
+    let var = |key: &str| {
+        env.iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.to_string())
+    };
+    config.apply_env(var).unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.listen.len(), 2);
+    assert_eq!(config.pages.results_per_page, 25);
+    assert_eq!(config.base_url.as_deref(), Some("https://example.com"));
+
+    config.pages.catalog_threads = 0;
+    config.rate_limit.search = "fast".to_string();
+    let err = format!("{:#}", config.validate().unwrap_err());
+    assert!(err.contains("pages.catalog_threads"));
+    assert!(err.contains("rate_limit.search"));
+
+    assert!(serde_yaml::from_str::<Config>("listen_on: []").is_err());
+}
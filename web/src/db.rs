@@ -0,0 +1,141 @@
+//! The database schema.
+//!
+//! The migrations in `migrations/` are compiled into the binary and applied at startup.
+//! The server refuses to run against a database whose schema differs from the one it was
+//! built for, e.g. because it was migrated by a newer version.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sqlx::{migrate::Migrator, SqlitePool};
+use tracing::info;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// Applies the migrations the database is missing
+pub async fn migrate(pool: &SqlitePool) -> anyhow::Result<()> {
+    MIGRATOR
+        .run(pool)
+        .await
+        .context("failed to migrate the database")?;
+    Ok(())
+}
+
+/// Fails unless exactly the migrations of this version were applied to the database
+pub async fn check_schema(pool: &SqlitePool) -> anyhow::Result<()> {
+    let has_migrations = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')",
+    )
+    .fetch_one(pool)
+    .await?;
+    let applied = if has_migrations {
+        sqlx::query_as::<_, (i64, Vec<u8>, bool)>(
+            "SELECT version, checksum, success FROM _sqlx_migrations ORDER BY version",
+        )
+        .fetch_all(pool)
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let problems = schema_problems(&applied);
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "the database schema doesn't match this version:\n  {}",
+            problems.join("\n  ")
+        );
+    }
+    if let Some(latest) = MIGRATOR.iter().last() {
+        info!("database schema is at version {}", latest.version);
+    }
+    Ok(())
+}
+
+/// What's wrong with the applied migrations, given as (version, checksum, success)
+fn schema_problems(applied: &[(i64, Vec<u8>, bool)]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut known: HashMap<_, _> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| (migration.version, migration))
+        .collect();
+
+    for (version, checksum, success) in applied {
+        match known.remove(version) {
+            None => problems.push(format!(
+                "migration {} is unknown, the database was migrated by a newer version",
+                version
+            )),
+            Some(_) if !success => problems.push(format!("migration {} failed", version)),
+            Some(migration) if *checksum != *migration.checksum => problems.push(format!(
+                "migration {} ({}) was changed after it was applied",
+                version, migration.description
+            )),
+            Some(_) => {}
+        }
+    }
+
+    let mut missing: Vec<_> = known.into_values().collect();
+    missing.sort_by_key(|migration| migration.version);
+    for migration in missing {
+        problems.push(format!(
+            "migration {} ({}) wasn't applied, leave out --no-migrate to apply it",
+            migration.version, migration.description
+        ));
+    }
+    problems
+}
+
+/// The tables, indexes and triggers in the database
+#[cfg(test)]
+async fn schema(pool: &SqlitePool) -> Vec<String> {
+    sqlx::query_scalar(
+        "SELECT type || ' ' || name || ': ' || coalesce(sql, '') FROM sqlite_master \
+        WHERE name NOT LIKE 'sqlite_%' AND name NOT LIKE '_sqlx_%' ORDER BY type, name",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_migrations() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    assert!(check_schema(&pool).await.is_err());
+
+    let versions: Vec<_> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .collect();
+    let mut schemas = vec![schema(&pool).await];
+    for &version in &versions {
+        let up_to = Migrator {
+            migrations: MIGRATOR
+                .iter()
+                .filter(|migration| migration.version <= version)
+                .cloned()
+                .collect(),
+            ignore_missing: false,
+        };
+        up_to.run(&pool).await.unwrap();
+        schemas.push(schema(&pool).await);
+    }
+    check_schema(&pool).await.unwrap();
+
+    // reverting a migration restores the schema from before it
+    for (i, version) in versions.iter().enumerate().rev() {
+        let previous = if i == 0 { 0 } else { versions[i - 1] };
+        MIGRATOR.undo(&pool, previous).await.unwrap();
+        assert_eq!(schema(&pool).await, schemas[i], "migration {}", version);
+        assert!(check_schema(&pool).await.is_err());
+    }
+
+    migrate(&pool).await.unwrap();
+    assert_eq!(Some(&schema(&pool).await), schemas.last());
+    check_schema(&pool).await.unwrap();
+}
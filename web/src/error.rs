@@ -15,6 +15,10 @@ impl IntoResponse for AppError {
             AppError::Anyhow(err) => {
                 if let Some(sqlx::Error::RowNotFound) = err.downcast_ref::<sqlx::Error>() {
                     status = StatusCode::NOT_FOUND;
+                } else if let Some(arkiv_storage::StorageError::NotFound) =
+                    err.downcast_ref::<arkiv_storage::StorageError>()
+                {
+                    status = StatusCode::NOT_FOUND;
                 } else {
                     status = StatusCode::INTERNAL_SERVER_ERROR;
                     tracing::error!("internal error occured: {}", err);
@@ -0,0 +1,586 @@
+use arkiv_storage::{Storage, StorageError};
+use axum::{
+    async_trait,
+    extract::{self, FromRequest, RequestParts},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use chrono::Utc;
+use http::{header, Method, StatusCode};
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+use tracing::{info, warn};
+
+use crate::{
+    auth::{self, Viewer},
+    config::Features,
+    error::{any_error, AppError},
+};
+
+const AUDIT_LOG_ENTRIES: i64 = 100;
+
+/// Credentials for the admin area, taken from the `admin` section of the config.
+/// Useful before any admin user exists.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// An admin, signed in as a user with admin rights, using a token with the `admin` scope
+/// or authenticated with HTTP basic auth against [`AdminCredentials`].
+///
+/// Browsers send cookies and basic auth with cross-site requests too, so anything but a GET
+/// has to come from the same origin.
+#[derive(Debug)]
+pub struct Admin {
+    name: String,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Admin {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let viewer = req
+            .extensions()
+            .get::<Viewer>()
+            .cloned()
+            .unwrap_or_default();
+        let name = match (
+            &viewer.user,
+            req.extensions().get::<Option<AdminCredentials>>(),
+        ) {
+            (Some(user), _) if viewer.is_admin() => user.username.clone(),
+            (Some(_), _) => return Err(StatusCode::FORBIDDEN.into_response()),
+            (None, Some(Some(credentials))) => {
+                let authorized = req
+                    .headers()
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_basic_auth)
+                    .map_or(false, |(username, password)| {
+                        constant_time_eq(username.as_bytes(), credentials.username.as_bytes())
+                            & constant_time_eq(password.as_bytes(), credentials.password.as_bytes())
+                    });
+                if !authorized {
+                    return Err((
+                        StatusCode::UNAUTHORIZED,
+                        [(header::WWW_AUTHENTICATE, "Basic realm=\"arkiv admin\"")],
+                    )
+                        .into_response());
+                }
+                credentials.username.clone()
+            }
+            (None, _) => {
+                let accounts = req
+                    .extensions()
+                    .get::<Features>()
+                    .map_or(false, |features| features.accounts);
+                // without accounts there is no way to sign in
+                return Err(if accounts {
+                    auth::login_redirect(req.uri().path()).into_response()
+                } else {
+                    StatusCode::NOT_FOUND.into_response()
+                });
+            }
+        };
+
+        if req.method() != Method::GET {
+            let host = req.headers().get(header::HOST);
+            let same_origin = req.headers().get(header::ORIGIN).map_or(true, |origin| {
+                let origin = origin.as_bytes();
+                let origin = origin
+                    .windows(3)
+                    .position(|w| w == b"://")
+                    .map_or(origin, |i| &origin[i + 3..]);
+                host.map_or(false, |host| host.as_bytes() == origin)
+            });
+            if !same_origin {
+                return Err(StatusCode::FORBIDDEN.into_response());
+            }
+        }
+
+        Ok(Admin { name })
+    }
+}
+
+/// Decodes the username and password of an `Authorization: Basic` header
+fn parse_basic_auth(value: &str) -> Option<(String, String)> {
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Things an admin can do to posts. The names are stored in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    HidePost,
+    UnhidePost,
+    HideThread,
+    UnhideThread,
+    PurgePost,
+    PurgeThread,
+    /// Deletes every copy of the post's file and blocks its MD5
+    PurgeMedia,
+    BlockMd5,
+    UnblockMd5,
+}
+
+impl AdminAction {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AdminAction::HidePost => "hide_post",
+            AdminAction::UnhidePost => "unhide_post",
+            AdminAction::HideThread => "hide_thread",
+            AdminAction::UnhideThread => "unhide_thread",
+            AdminAction::PurgePost => "purge_post",
+            AdminAction::PurgeThread => "purge_thread",
+            AdminAction::PurgeMedia => "purge_media",
+            AdminAction::BlockMd5 => "block_md5",
+            AdminAction::UnblockMd5 => "unblock_md5",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEntry {
+    time: i64,
+    actor: String,
+    action: String,
+    board: Option<String>,
+    no: Option<i64>,
+    md5: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BlockedMd5 {
+    md5: String,
+    reason: Option<String>,
+    created_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostRef {
+    board: String,
+    no: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostActionForm {
+    board: String,
+    no: i64,
+    action: AdminAction,
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlocklistForm {
+    md5: String,
+    action: AdminAction,
+    #[serde(default)]
+    reason: String,
+}
+
+/// A stored file that has to be deleted once the database changes are committed
+struct MediaFile {
+    board: String,
+    tim: Option<i64>,
+    ext: Option<String>,
+}
+
+/// The audit log and the MD5 blocklist
+pub async fn get_admin(
+    _admin: Admin,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+) -> Result<Html<String>, AppError> {
+    let audit_log = query_as!(
+        AuditEntry,
+        r#"
+        SELECT time, actor, action, board, no, md5, reason FROM audit_log
+        ORDER BY id DESC LIMIT ?
+        "#,
+        AUDIT_LOG_ENTRIES
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+    let blocklist = query_as!(
+        BlockedMd5,
+        "SELECT md5, reason, created_at FROM md5_blocklist ORDER BY created_at DESC"
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let mut context = Context::new();
+    context.insert("audit_log", &audit_log);
+    context.insert("blocklist", &blocklist);
+
+    Ok(Html(t.render("admin.html", &context).map_err(any_error)?))
+}
+
+/// A single post, hidden or not, with the actions that can be taken on it
+pub async fn get_admin_post(
+    _admin: Admin,
+    extract::Query(post): extract::Query<PostRef>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+) -> Result<Html<String>, AppError> {
+    let post = query_as!(
+        fourchan::Post,
+        "SELECT * FROM posts WHERE board = ? AND no = ?",
+        post.board,
+        post.no
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(any_error)?;
+    let blocked = match &post.md5 {
+        Some(md5) => query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM md5_blocklist WHERE md5 = ?) as "blocked!: bool""#,
+            md5
+        )
+        .fetch_one(&pool)
+        .await
+        .map_err(any_error)?,
+        None => false,
+    };
+    let audit_log = query_as!(
+        AuditEntry,
+        r#"
+        SELECT time, actor, action, board, no, md5, reason FROM audit_log
+        WHERE board = ? AND no = ?
+        ORDER BY id DESC
+        "#,
+        post.board,
+        post.no
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let mut context = Context::new();
+    context.insert("board", &post.board);
+    context.insert(
+        "thread",
+        &if post.resto == 0 { post.no } else { post.resto },
+    );
+    context.insert("post", &post);
+    context.insert("poster_id", &None::<String>);
+    context.insert("blocked", &blocked);
+    context.insert("audit_log", &audit_log);
+
+    Ok(Html(
+        t.render("admin_post.html", &context).map_err(any_error)?,
+    ))
+}
+
+/// Hides, unhides or purges a post, its thread or its media
+#[allow(clippy::too_many_lines)]
+pub async fn post_admin_action<S: Storage>(
+    admin: Admin,
+    extract::Form(form): extract::Form<PostActionForm>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(storage): extract::Extension<S>,
+) -> Result<Redirect, AppError> {
+    let mut tx = pool.begin().await.map_err(any_error)?;
+
+    let post = query!(
+        "SELECT resto, md5 FROM posts WHERE board = ? AND no = ?",
+        form.board,
+        form.no
+    )
+    .fetch_one(&mut tx)
+    .await
+    .map_err(any_error)?;
+    let thread = if post.resto == 0 { form.no } else { post.resto };
+    let reason = Some(form.reason.trim()).filter(|reason| !reason.is_empty());
+
+    let mut files = Vec::new();
+    match form.action {
+        AdminAction::HidePost | AdminAction::UnhidePost => {
+            let hidden = form.action == AdminAction::HidePost;
+            query!(
+                "UPDATE posts SET hidden = ? WHERE board = ? AND no = ?",
+                hidden,
+                form.board,
+                form.no
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(any_error)?;
+        }
+        AdminAction::HideThread | AdminAction::UnhideThread => {
+            let hidden = form.action == AdminAction::HideThread;
+            query!(
+                "UPDATE posts SET hidden = ? WHERE board = ? AND (no = ? OR resto = ?)",
+                hidden,
+                form.board,
+                thread,
+                thread
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(any_error)?;
+        }
+        AdminAction::PurgePost | AdminAction::PurgeThread => {
+            let whole_thread = form.action == AdminAction::PurgeThread;
+            let no = if whole_thread { thread } else { form.no };
+            files = query_as!(
+                MediaFile,
+                r#"
+                SELECT board, tim, ext FROM posts
+                WHERE board = ?1 AND (no = ?2 OR (?3 AND resto = ?2)) AND tim IS NOT NULL
+                "#,
+                form.board,
+                no,
+                whole_thread
+            )
+            .fetch_all(&mut tx)
+            .await
+            .map_err(any_error)?;
+
+            // quotes of the purged posts would show up as backlinks to nowhere
+            query!(
+                r#"
+                DELETE FROM post_links WHERE target_board = ?1 AND target_post IN
+                    (SELECT no FROM posts WHERE board = ?1 AND (no = ?2 OR (?3 AND resto = ?2)))
+                "#,
+                form.board,
+                no,
+                whole_thread
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(any_error)?;
+            // posts_fts and the stats are kept in sync by triggers
+            query!(
+                "DELETE FROM posts WHERE board = ?1 AND (no = ?2 OR (?3 AND resto = ?2))",
+                form.board,
+                no,
+                whole_thread
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(any_error)?;
+            query!(
+                "DELETE FROM post_links WHERE board = ?1 AND (no = ?2 OR (?3 AND thread = ?2))",
+                form.board,
+                no,
+                whole_thread
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(any_error)?;
+            query!(
+                "INSERT OR IGNORE INTO purged_posts (board, no) VALUES (?, ?)",
+                form.board,
+                no
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(any_error)?;
+        }
+        AdminAction::PurgeMedia => {
+            let md5 = post
+                .md5
+                .clone()
+                .ok_or(AppError::Status(StatusCode::BAD_REQUEST))?;
+            files = query_as!(
+                MediaFile,
+                "SELECT board, tim, ext FROM posts WHERE md5 = ?",
+                md5
+            )
+            .fetch_all(&mut tx)
+            .await
+            .map_err(any_error)?;
+
+            query!("UPDATE posts SET media_removed = 1 WHERE md5 = ?", md5)
+                .execute(&mut tx)
+                .await
+                .map_err(any_error)?;
+            block_md5(&mut tx, &md5, reason).await?;
+        }
+        AdminAction::BlockMd5 | AdminAction::UnblockMd5 => {
+            return Err(AppError::Status(StatusCode::BAD_REQUEST));
+        }
+    }
+
+    for file in &files {
+        query!(
+            "DELETE FROM image_hashes WHERE board = ? AND tim = ?",
+            file.board,
+            file.tim
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(any_error)?;
+        query!(
+            "DELETE FROM generated_thumbnails WHERE board = ? AND tim = ?",
+            file.board,
+            file.tim
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(any_error)?;
+        query!(
+            "DELETE FROM skipped_media WHERE board = ? AND tim = ?",
+            file.board,
+            file.tim
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(any_error)?;
+    }
+
+    let md5 = if form.action == AdminAction::PurgeMedia {
+        post.md5
+    } else {
+        None
+    };
+    audit(
+        &mut tx,
+        &admin,
+        form.action,
+        Some((&form.board, form.no)),
+        md5.as_deref(),
+        reason,
+    )
+    .await?;
+    tx.commit().await.map_err(any_error)?;
+
+    delete_files(&storage, &files).await;
+
+    match form.action {
+        AdminAction::PurgePost | AdminAction::PurgeThread => Ok(Redirect::to("/admin")),
+        _ => Ok(Redirect::to(&format!(
+            "/admin/post?board={}&no={}",
+            form.board, form.no
+        ))),
+    }
+}
+
+/// Adds an MD5 to the blocklist or removes it
+pub async fn post_blocklist(
+    admin: Admin,
+    extract::Form(form): extract::Form<BlocklistForm>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+) -> Result<Redirect, AppError> {
+    let md5 = form.md5.trim();
+    if md5.is_empty() {
+        return Err(AppError::Status(StatusCode::BAD_REQUEST));
+    }
+    let reason = Some(form.reason.trim()).filter(|reason| !reason.is_empty());
+
+    let mut tx = pool.begin().await.map_err(any_error)?;
+    match form.action {
+        AdminAction::BlockMd5 => block_md5(&mut tx, md5, reason).await?,
+        AdminAction::UnblockMd5 => {
+            query!("DELETE FROM md5_blocklist WHERE md5 = ?", md5)
+                .execute(&mut tx)
+                .await
+                .map_err(any_error)?;
+        }
+        _ => return Err(AppError::Status(StatusCode::BAD_REQUEST)),
+    }
+    audit(&mut tx, &admin, form.action, None, Some(md5), reason).await?;
+    tx.commit().await.map_err(any_error)?;
+
+    Ok(Redirect::to("/admin"))
+}
+
+async fn block_md5(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    md5: &str,
+    reason: Option<&str>,
+) -> Result<(), AppError> {
+    let created_at = Utc::now().timestamp();
+    query!(
+        r#"
+        INSERT INTO md5_blocklist (md5, reason, created_at) VALUES (?, ?, ?)
+        ON CONFLICT(md5) DO NOTHING
+        "#,
+        md5,
+        reason,
+        created_at
+    )
+    .execute(tx)
+    .await
+    .map_err(any_error)?;
+
+    Ok(())
+}
+
+async fn audit(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    admin: &Admin,
+    action: AdminAction,
+    post: Option<(&str, i64)>,
+    md5: Option<&str>,
+    reason: Option<&str>,
+) -> Result<(), AppError> {
+    info!(admin = %admin.name, action = action.as_str(), ?post, ?md5, "admin action");
+
+    let time = Utc::now().timestamp();
+    let action = action.as_str();
+    let (board, no) = post.unzip();
+    query!(
+        r#"
+        INSERT INTO audit_log (time, actor, action, board, no, md5, reason)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        time,
+        admin.name,
+        action,
+        board,
+        no,
+        md5,
+        reason
+    )
+    .execute(tx)
+    .await
+    .map_err(any_error)?;
+
+    Ok(())
+}
+
+/// Deletes the media and thumbnails of purged posts. Failures are only logged, the
+/// posts are gone already and the files are no longer served.
+async fn delete_files<S: Storage>(storage: &S, files: &[MediaFile]) {
+    for file in files {
+        let (tim, ext) = match (file.tim, &file.ext) {
+            (Some(tim), Some(ext)) => (tim, ext),
+            _ => continue,
+        };
+        for key in [format!("{}{}", tim, ext), format!("{}s.jpg", tim)] {
+            match storage.delete(&key, Some(&file.board)).await {
+                Ok(()) | Err(StorageError::NotFound) => {}
+                Err(err) => warn!("failed to delete /{}/{}: {}", file.board, key, err),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_basic_auth() {
+    assert_eq!(
+        parse_basic_auth("Basic YWRtaW46aHVudGVyOjI="),
+        Some(("admin".to_string(), "hunter:2".to_string()))
+    );
+    assert_eq!(parse_basic_auth("Bearer YWRtaW46aHVudGVyMg=="), None);
+    assert_eq!(parse_basic_auth("Basic not base64"), None);
+    assert!(constant_time_eq(b"hunter2", b"hunter2"));
+    assert!(!constant_time_eq(b"hunter2", b"hunter3"));
+    assert!(!constant_time_eq(b"hunter2", b"hunter"));
+}
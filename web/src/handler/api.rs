@@ -0,0 +1,194 @@
+use std::collections::{HashSet, VecDeque};
+
+use axum::{extract, Json};
+use sqlx::SqlitePool;
+
+use crate::{
+    auth::Viewer,
+    error::{any_error, AppError},
+};
+
+/// Upper bound on the number of replies returned in a reply tree
+const MAX_REPLY_TREE_SIZE: usize = 1000;
+
+/// A post and the posts that quote it, recursively
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ReplyNode {
+    pub board: String,
+    pub thread: i64,
+    pub no: i64,
+    pub replies: Vec<ReplyNode>,
+}
+
+#[derive(Debug)]
+struct Link {
+    board: String,
+    no: i64,
+    thread: i64,
+    target_board: String,
+    target_post: i64,
+}
+
+pub async fn get_reply_tree(
+    extract::Path((board, no)): extract::Path<(String, i64)>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    viewer: Viewer,
+) -> Result<Json<ReplyNode>, AppError> {
+    let private = viewer.can_read_private();
+    let resto = query_scalar!(
+        "SELECT resto FROM posts WHERE board = ? AND no = ? AND hidden = 0",
+        board,
+        no
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let links = reply_links(&pool, &board, no, private)
+        .await
+        .map_err(any_error)?;
+
+    let thread = if resto == 0 { no } else { resto };
+    Ok(Json(build_tree(board, thread, no, &links)))
+}
+
+/// Collects the quotes of `no` and of the posts quoting it, breadth first, until there are
+/// [`MAX_REPLY_TREE_SIZE`] of them
+async fn reply_links(
+    pool: &SqlitePool,
+    board: &str,
+    no: i64,
+    private: bool,
+) -> sqlx::Result<Vec<Link>> {
+    let mut links = Vec::new();
+    let mut visited = HashSet::from([(board.to_string(), no)]);
+    let mut queue = VecDeque::from([(board.to_string(), no)]);
+    'tree: while let Some((target_board, target_post)) = queue.pop_front() {
+        let replies = query_as!(
+            Link,
+            r#"
+            SELECT board, no, thread, target_board, target_post FROM post_links
+            WHERE target_board = ? AND target_post = ?
+                AND NOT EXISTS (SELECT 1 FROM posts p
+                    WHERE p.board = post_links.board AND p.no = post_links.no AND p.hidden = 1)
+                AND (? OR post_links.board NOT IN private_boards)
+            ORDER BY board, no
+            "#,
+            target_board,
+            target_post,
+            private
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for reply in replies {
+            if visited.insert((reply.board.clone(), reply.no)) {
+                queue.push_back((reply.board.clone(), reply.no));
+            }
+            links.push(reply);
+            // the queued posts aren't looked up either
+            if links.len() >= MAX_REPLY_TREE_SIZE {
+                break 'tree;
+            }
+        }
+    }
+
+    Ok(links)
+}
+
+fn build_tree(board: String, thread: i64, no: i64, links: &[Link]) -> ReplyNode {
+    fn children(
+        board: &str,
+        no: i64,
+        links: &[Link],
+        visited: &mut HashSet<(String, i64)>,
+    ) -> Vec<ReplyNode> {
+        let mut replies = Vec::new();
+        for link in links {
+            if link.target_board != board || link.target_post != no {
+                continue;
+            }
+            if !visited.insert((link.board.clone(), link.no)) {
+                continue;
+            }
+            replies.push(ReplyNode {
+                board: link.board.clone(),
+                thread: link.thread,
+                no: link.no,
+                replies: children(&link.board, link.no, links, visited),
+            });
+        }
+        replies
+    }
+
+    let mut visited = HashSet::from([(board.clone(), no)]);
+    let replies = children(&board, no, links, &mut visited);
+    ReplyNode {
+        board,
+        thread,
+        no,
+        replies,
+    }
+}
+
+#[test]
+fn test_build_tree() {
+    let link = |board: &str, no, target_post| Link {
+        board: board.to_string(),
+        no,
+        thread: 1,
+        target_board: "g".to_string(),
+        target_post,
+    };
+    let node = |board: &str, no, replies| ReplyNode {
+        board: board.to_string(),
+        thread: 1,
+        no,
+        replies,
+    };
+    let links = [
+        link("g", 2, 1),
+        link("g", 3, 2),
+        link("b", 4, 1),
+        link("g", 1, 3),
+    ];
+
+    assert_eq!(
+        build_tree("g".to_string(), 1, 1, &links),
+        node(
+            "g",
+            1,
+            vec![
+                node("g", 2, vec![node("g", 3, vec![])]),
+                node("b", 4, vec![])
+            ]
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_reply_links_limit() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+
+    // 1500 replies to the post, each with a reply of its own
+    sqlx::query(
+        r#"
+        WITH RECURSIVE n(no) AS (SELECT 2 UNION ALL SELECT no + 1 FROM n WHERE no < 1501)
+        INSERT INTO post_links (board, no, thread, target_board, target_thread, target_post)
+        SELECT 'g', no, 1, 'g', 1, 1 FROM n
+        UNION ALL SELECT 'g', no + 10000, 1, 'g', 1, no FROM n
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let links = reply_links(&pool, "g", 1, false).await.unwrap();
+    assert_eq!(links.len(), MAX_REPLY_TREE_SIZE);
+    assert!(links.iter().all(|link| link.target_post == 1));
+}
@@ -1,55 +1,298 @@
 use axum::{extract, response::Html};
 use fourchan::Post;
 use sqlx::SqlitePool;
-use tera::{Tera, Context};
+use tera::{Context, Tera};
 
-use crate::{error::{AppError, any_error}, Pagination, THREADS_PER_PAGE};
+use crate::{
+    config::PageSizes,
+    error::{any_error, AppError},
+    util::page_offset,
+    Pagination,
+};
 
+/// A window of threads on a board, newest first
+#[derive(Debug, Serialize)]
+pub struct BoardPage {
+    pub threads: Vec<Post>,
+
+    /// Token for the `before` parameter of the previous page
+    pub prev: Option<i64>,
+
+    /// Token for the `after` parameter of the next page
+    pub next: Option<i64>,
+
+    /// Number of the page the first thread is on
+    pub page: i64,
+    pub pages: i64,
+}
 
 pub async fn get_board(
     extract::Path(board): extract::Path<String>,
     extract::Query(pagination): extract::Query<Pagination>,
     extract::Extension(pool): extract::Extension<SqlitePool>,
     extract::Extension(t): extract::Extension<Tera>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
 ) -> Result<Html<String>, AppError> {
-    let threads = if let Some(before) = pagination.before {
-        query_as!(
+    let page = board_page(&pool, &board, &pagination, pages.threads_per_page).await?;
+
+    let mut context = Context::new();
+    context.insert("board", &board);
+    context.insert("page_numbers", &page_numbers(page.page, page.pages));
+    context.insert("page", &page);
+
+    Ok(Html(t.render("board.html", &context).map_err(any_error)?))
+}
+
+/// Loads the threads of `board` selected by `pagination`.
+///
+/// `before` and `after` are keyset tokens that select the threads directly before or
+/// after the thread with that number, `page` jumps to a page number. Tokens take
+/// precedence over the page number.
+pub async fn board_page(
+    pool: &SqlitePool,
+    board: &str,
+    pagination: &Pagination,
+    per_page: i64,
+) -> Result<BoardPage, AppError> {
+    let mut threads = match (pagination.before, pagination.after) {
+        (Some(before), _) => {
+            let mut threads = query_as!(
+                Post,
+                r#"SELECT * FROM posts WHERE resto = 0 AND hidden = 0 AND board = ? AND no > ? ORDER BY no ASC LIMIT ?"#,
+                board,
+                before,
+                per_page
+            )
+            .fetch_all(pool)
+            .await
+            .map_err(any_error)?;
+            threads.reverse();
+            threads
+        }
+        (None, Some(after)) => {
+            query_as!(
+                Post,
+                r#"SELECT * FROM posts WHERE resto = 0 AND hidden = 0 AND board = ? AND no < ? ORDER BY no DESC LIMIT ?"#,
+                board,
+                after,
+                per_page
+            )
+            .fetch_all(pool)
+            .await
+            .map_err(any_error)?
+        }
+        (None, None) => Vec::new(),
+    };
+
+    // paging back to the top can leave a partial window, show the full first page instead
+    let partial_first_page =
+        pagination.before.is_some() && i64::try_from(threads.len()).unwrap_or(i64::MAX) < per_page;
+    if threads.is_empty() || partial_first_page {
+        let page = if pagination.before.is_some() || pagination.after.is_some() {
+            None
+        } else {
+            pagination.page
+        };
+        let (_, offset) = page_offset(page, per_page)?;
+        threads = query_as!(
             Post,
-            r#"SELECT * FROM posts WHERE resto = 0 AND board = ? AND no > ? ORDER BY no DESC LIMIT ?"# ,
+            r#"SELECT * FROM posts WHERE resto = 0 AND hidden = 0 AND board = ? ORDER BY no DESC LIMIT ? OFFSET ?"#,
             board,
-            before,
-            THREADS_PER_PAGE
+            per_page,
+            offset
         )
-        .fetch_all(&pool)
+        .fetch_all(pool)
         .await
-    } else if let Some(after) = pagination.after {
-        query_as!(
-            Post,
-            r#"SELECT * FROM posts WHERE resto = 0 AND board = ? AND no < ? ORDER BY no DESC LIMIT ?"# ,
-            board,
-            after,
-            THREADS_PER_PAGE,
+        .map_err(any_error)?;
+    }
+
+    let total = i64::from(
+        query_scalar!(
+            r#"SELECT count(*) FROM posts WHERE resto = 0 AND hidden = 0 AND board = ?"#,
+            board
         )
-        .fetch_all(&pool)
+        .fetch_one(pool)
         .await
-    } else {
-        query_as!(
-            Post,
-            r#"SELECT * FROM posts WHERE resto = 0 AND board = ? ORDER BY no DESC LIMIT ?"# ,
-            board,
-            THREADS_PER_PAGE
+        .map_err(any_error)?,
+    );
+    let newer = match threads.first() {
+        Some(first) => i64::from(
+            query_scalar!(
+                r#"SELECT count(*) FROM posts WHERE resto = 0 AND hidden = 0 AND board = ? AND no > ?"#,
+                board,
+                first.no
+            )
+            .fetch_one(pool)
+            .await
+            .map_err(any_error)?,
+        ),
+        None => 0,
+    };
+
+    let shown = i64::try_from(threads.len()).unwrap_or(i64::MAX);
+    let prev = threads.first().filter(|_| newer > 0).map(|t| t.no);
+    let next = threads
+        .last()
+        .filter(|_| newer + shown < total)
+        .map(|t| t.no);
+
+    Ok(BoardPage {
+        threads,
+        prev,
+        next,
+        page: newer / per_page + 1,
+        pages: ((total + per_page - 1) / per_page).max(1),
+    })
+}
+
+/// Page numbers to link to: the first and last page and the pages around `current`.
+/// Gaps between them are `None`.
+fn page_numbers(current: i64, pages: i64) -> Vec<Option<i64>> {
+    let mut numbers = Vec::new();
+    let mut last = 0;
+    for page in 1..=pages {
+        if page == 1 || page == pages || (page - current).abs() <= 2 {
+            if page > last + 1 {
+                numbers.push(None);
+            }
+            numbers.push(Some(page));
+            last = page;
+        }
+    }
+    numbers
+}
+
+#[test]
+fn test_page_numbers() {
+    assert_eq!(page_numbers(1, 1), vec![Some(1)]);
+    assert_eq!(
+        page_numbers(1, 10),
+        vec![Some(1), Some(2), Some(3), None, Some(10)]
+    );
+    assert_eq!(
+        page_numbers(6, 10),
+        vec![
+            Some(1),
+            None,
+            Some(4),
+            Some(5),
+            Some(6),
+            Some(7),
+            Some(8),
+            None,
+            Some(10)
+        ]
+    );
+}
+
+/// Creates an in-memory database with the threads `1..=threads` on /g/
+#[cfg(test)]
+async fn fixture(threads: i64) -> SqlitePool {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+
+    for no in 1..=threads {
+        query!(
+            r#"
+            INSERT INTO posts (no, resto, sticky, closed, now, time, name, filedeleted,
+                spoiler, bumplimit, imagelimit, m_img, archived, board)
+            VALUES (?, 0, 0, 0, '', ?, 'Anonymous', 0, 0, 0, 0, 0, 0, 'g')
+            "#,
+            no,
+            no
         )
-        .fetch_all(&pool)
+        .execute(&pool)
         .await
+        .unwrap();
     }
-    .map_err(any_error)?;
 
-    let mut context = Context::new();
-    context.insert("threads", &threads);
-    context.insert("board", &board);
-    context.insert("first_thread", &threads.get(0).map(|t| t.no));
-    context.insert("last_thread", &threads.iter().last().map(|t| t.no));
+    pool
+}
 
-    Ok(Html(t.render("board.html", &context).map_err(any_error)?))
+#[cfg(test)]
+fn thread_numbers(page: &BoardPage) -> Vec<i64> {
+    page.threads.iter().map(|t| t.no).collect()
 }
 
+#[tokio::test]
+async fn test_board_page_keyset() {
+    let pool = fixture(10).await;
+    let pagination = |before, after, page| Pagination {
+        before,
+        after,
+        page,
+    };
+
+    let first = board_page(&pool, "g", &pagination(None, None, None), 4)
+        .await
+        .unwrap();
+    assert_eq!(thread_numbers(&first), vec![10, 9, 8, 7]);
+    assert_eq!(
+        (first.prev, first.next, first.page, first.pages),
+        (None, Some(7), 1, 3)
+    );
+
+    let second = board_page(&pool, "g", &pagination(None, first.next, None), 4)
+        .await
+        .unwrap();
+    assert_eq!(thread_numbers(&second), vec![6, 5, 4, 3]);
+    assert_eq!(
+        (second.prev, second.next, second.page),
+        (Some(6), Some(3), 2)
+    );
+
+    let last = board_page(&pool, "g", &pagination(None, second.next, None), 4)
+        .await
+        .unwrap();
+    assert_eq!(thread_numbers(&last), vec![2, 1]);
+    assert_eq!((last.prev, last.next, last.page), (Some(2), None, 3));
+
+    // paging backwards returns the threads nearest to the token
+    let back = board_page(&pool, "g", &pagination(last.prev, None, None), 4)
+        .await
+        .unwrap();
+    assert_eq!(thread_numbers(&back), vec![6, 5, 4, 3]);
+
+    let top = board_page(&pool, "g", &pagination(Some(8), None, None), 4)
+        .await
+        .unwrap();
+    assert_eq!(thread_numbers(&top), vec![10, 9, 8, 7]);
+    assert_eq!(top.prev, None);
+
+    let jump = board_page(&pool, "g", &pagination(None, None, Some(3)), 4)
+        .await
+        .unwrap();
+    assert_eq!(thread_numbers(&jump), vec![2, 1]);
+}
+
+#[tokio::test]
+async fn test_get_board_links() {
+    let pool = fixture(3).await;
+    let t = crate::assets::templates(
+        &crate::assets::Assets::templates(None, false),
+        crate::config::Features::default(),
+    )
+    .unwrap();
+
+    let Html(html) = get_board(
+        extract::Path("g".to_string()),
+        extract::Query(Pagination {
+            before: None,
+            after: None,
+            page: None,
+        }),
+        extract::Extension(pool),
+        extract::Extension(t),
+        extract::Extension(PageSizes::default()),
+    )
+    .await
+    .unwrap();
+
+    assert!(html.contains(r#"href="/g/thread/3""#));
+    assert!(!html.contains("?before="));
+    assert!(!html.contains("?after="));
+}
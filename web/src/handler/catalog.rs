@@ -0,0 +1,104 @@
+use axum::{extract, response::Html};
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::{
+    config::PageSizes,
+    error::{any_error, AppError},
+};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatalogSort {
+    #[default]
+    Created,
+    LastReply,
+    Replies,
+    Images,
+}
+
+impl CatalogSort {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CatalogSort::Created => "created",
+            CatalogSort::LastReply => "last_reply",
+            CatalogSort::Replies => "replies",
+            CatalogSort::Images => "images",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogQuery {
+    #[serde(default)]
+    sort: CatalogSort,
+}
+
+/// An OP with counts aggregated from the archived replies
+#[derive(Debug, Serialize)]
+struct CatalogThread {
+    no: i64,
+    time: i64,
+    sub: Option<String>,
+    com: Option<String>,
+    tim: Option<i64>,
+    tn_w: Option<i64>,
+    tn_h: Option<i64>,
+    spoiler: i64,
+    filedeleted: i64,
+    media_removed: i64,
+    sticky: i64,
+    closed: i64,
+    replies: i64,
+    images: i64,
+    last_reply: i64,
+}
+
+pub async fn get_catalog(
+    extract::Path(board): extract::Path<String>,
+    extract::Query(query): extract::Query<CatalogQuery>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
+) -> Result<Html<String>, AppError> {
+    let sort = query.sort.as_str();
+    let threads = query_as!(
+        CatalogThread,
+        r#"
+        SELECT op.no, op.time, op.sub, op.com, op.tim, op.tn_w, op.tn_h, op.spoiler,
+            op.filedeleted, op.media_removed, op.sticky, op.closed,
+            (SELECT count(*) FROM posts r
+                WHERE r.board = op.board AND r.resto = op.no AND r.hidden = 0)
+                as "replies!: i64",
+            (SELECT count(r.tim) FROM posts r
+                WHERE r.board = op.board AND r.resto = op.no AND r.hidden = 0)
+                as "images!: i64",
+            (SELECT ifnull(max(r.time), op.time) FROM posts r
+                WHERE r.board = op.board AND r.resto = op.no AND r.hidden = 0)
+                as "last_reply!: i64"
+        FROM posts op
+        WHERE op.board = ? AND op.resto = 0 AND op.hidden = 0
+        ORDER BY CASE ?
+            WHEN 'last_reply' THEN "last_reply!: i64"
+            WHEN 'replies' THEN "replies!: i64"
+            WHEN 'images' THEN "images!: i64"
+            ELSE op.no
+        END DESC, op.no DESC
+        LIMIT ?
+        "#,
+        board,
+        sort,
+        pages.catalog_threads
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let mut context = Context::new();
+    context.insert("board", &board);
+    context.insert("sort", sort);
+    context.insert("threads", &threads);
+
+    Ok(Html(t.render("catalog.html", &context).map_err(any_error)?))
+}
@@ -1,15 +1,44 @@
+use std::ops::Range;
+
 use anyhow::Context;
-use arkiv_storage::Storage;
-use axum::{extract, response::IntoResponse};
-use http::{header, HeaderMap};
+use arkiv_storage::{Storage, StorageError};
+use axum::{
+    body::StreamBody,
+    extract,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use sqlx::SqlitePool;
+use tokio_util::io::ReaderStream;
+
+use crate::error::{any_error, AppError};
 
-use crate::error::{AppError, any_error};
+/// Stored files never change once written, so clients may cache them for a year
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
 
+/// Served in place of thumbnails that were never archived
+const PLACEHOLDER_THUMBNAIL: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="125" height="125" viewBox="0 0 125 125"><rect width="125" height="125" fill="#1a1a1a"/><text x="62.5" y="67" fill="#777" font-family="monospace" font-size="12" text-anchor="middle">no thumbnail</text></svg>"##;
 
 pub async fn cdn<S: Storage>(
     extract::Path((board, key)): extract::Path<(String, String)>,
     extract::Extension(storage): extract::Extension<S>,
-) -> Result<impl IntoResponse, AppError> {
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    request_headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if is_hidden(&pool, &board, &key).await? {
+        return Err(AppError::Status(StatusCode::NOT_FOUND));
+    }
+
+    let metadata = match storage.metadata(&key, Some(&board)).await {
+        Ok(metadata) => metadata,
+        Err(StorageError::NotFound) => return missing_file(&storage, &board, &key).await,
+        Err(err) => return Err(any_error(err)),
+    };
+
+    // files are keyed by their upload timestamp, which makes the key a stable validator
+    let etag = format!("\"{}\"", key);
+
     let content_type = mime_guess::from_path(&key).first_or_octet_stream();
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -27,9 +56,213 @@ pub async fn cdn<S: Storage>(
             .context("failed to parse content type ehader")
             .map_err(any_error)?,
     );
+    headers.insert(
+        header::ETAG,
+        etag.parse()
+            .context("failed to parse etag header")
+            .map_err(any_error)?,
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(modified) = metadata.modified {
+        let modified = DateTime::<Utc>::from(modified)
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        headers.insert(
+            header::LAST_MODIFIED,
+            modified
+                .parse()
+                .context("failed to parse last modified header")
+                .map_err(any_error)?,
+        );
+    }
+
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .map_or(false, |if_none_match| etag_matches(if_none_match, &etag));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let range = match request_headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(range) => parse_range(range, metadata.len),
+        None => None,
+    };
+
+    match range {
+        Some(Ok(range)) => {
+            headers.insert(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, metadata.len)
+                    .parse()
+                    .context("failed to parse content range header")
+                    .map_err(any_error)?,
+            );
+            let body = stream(&storage, &board, &key, range).await?;
+
+            Ok((StatusCode::PARTIAL_CONTENT, headers, body).into_response())
+        }
+        Some(Err(())) => {
+            headers.insert(
+                header::CONTENT_RANGE,
+                format!("bytes */{}", metadata.len)
+                    .parse()
+                    .context("failed to parse content range header")
+                    .map_err(any_error)?,
+            );
+
+            Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response())
+        }
+        None => {
+            let body = stream(&storage, &board, &key, 0..metadata.len).await?;
+
+            Ok((headers, body).into_response())
+        }
+    }
+}
 
-    let body = storage.get(&key, Some(&board)).await.map_err(any_error)?;
+/// Streams a part of a stored file, so large files are never held in memory
+async fn stream<S: Storage>(
+    storage: &S,
+    board: &str,
+    key: &str,
+    range: Range<u64>,
+) -> Result<Response, AppError> {
+    let len = range.end - range.start;
+    let reader = storage
+        .get_range(key, Some(board), range)
+        .await
+        .map_err(any_error)?;
 
-    Ok((headers, body))
+    let mut response = StreamBody::new(ReaderStream::new(reader)).into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    Ok(response)
 }
 
+/// Whether the file belongs to a post the admins hid
+async fn is_hidden(pool: &SqlitePool, board: &str, key: &str) -> Result<bool, AppError> {
+    let tim = key
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|tim| tim.parse::<i64>().ok());
+    let tim = match tim {
+        Some(tim) => tim,
+        None => return Ok(false),
+    };
+    let hidden = query_scalar!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM posts WHERE board = ? AND tim = ? AND hidden = 1)
+            as "hidden!: bool"
+        "#,
+        board,
+        tim
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(any_error)?;
+
+    Ok(hidden)
+}
+
+/// Builds the response for a file that isn't in the storage.
+///
+/// Missing thumbnails get a placeholder image. Missing full media falls back to its
+/// thumbnail, which exists when the board was archived without `full_media`. The
+/// fallback may be replaced by a backfilled file later on, so it isn't cached.
+async fn missing_file<S: Storage>(
+    storage: &S,
+    board: &str,
+    key: &str,
+) -> Result<Response, AppError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    let tim = key.split('.').next().unwrap_or_default();
+    if tim.ends_with('s') {
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("image/svg+xml"),
+        );
+        return Ok((StatusCode::NOT_FOUND, headers, PLACEHOLDER_THUMBNAIL).into_response());
+    }
+    if tim.is_empty() || !tim.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(AppError::Status(StatusCode::NOT_FOUND));
+    }
+
+    let body = storage
+        .get(&format!("{}s.jpg", tim), Some(board))
+        .await
+        .map_err(any_error)?;
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/jpeg"));
+
+    Ok((headers, body).into_response())
+}
+
+fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    if_none_match.to_str().map_or(false, |value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    })
+}
+
+/// Parses a `Range` header into the byte range it requests from a file of size `len`.
+///
+/// Returns `None` if the header should be ignored (unknown unit, multiple ranges or
+/// malformed syntax) and `Some(Err(()))` if the range can't be satisfied.
+fn parse_range(header: &str, len: u64) -> Option<Result<Range<u64>, ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        // an empty file has no bytes to send, whatever the suffix
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        len.saturating_sub(suffix)..len
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            len
+        } else {
+            let end: u64 = end.parse().ok()?;
+            if end < start {
+                return None;
+            }
+            end.saturating_add(1).min(len)
+        };
+        if start >= len {
+            return Some(Err(()));
+        }
+        start..end
+    };
+
+    Some(Ok(range))
+}
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(parse_range("bytes=0-99", 1000), Some(Ok(0..100)));
+    assert_eq!(parse_range("bytes=500-", 1000), Some(Ok(500..1000)));
+    assert_eq!(parse_range("bytes=-200", 1000), Some(Ok(800..1000)));
+    assert_eq!(parse_range("bytes=900-2000", 1000), Some(Ok(900..1000)));
+    assert_eq!(parse_range("bytes=1000-", 1000), Some(Err(())));
+    assert_eq!(parse_range("bytes=-5", 0), Some(Err(())));
+    assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+    assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+    assert_eq!(parse_range("items=0-1", 1000), None);
+}
@@ -0,0 +1,294 @@
+use std::fmt::Write;
+
+use axum::{
+    extract,
+    response::{IntoResponse, Response},
+};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use fourchan::{Comment, Post};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use sqlx::SqlitePool;
+
+use crate::{
+    auth::Viewer,
+    error::{any_error, AppError},
+    handler::search::{search_posts, SearchQuery},
+    util::comment_html,
+};
+
+/// Number of posts in a feed
+const FEED_ENTRIES: i64 = 50;
+
+/// Length entry titles taken from comments are cut to
+const TITLE_LENGTH: usize = 80;
+
+/// The `base_url` from the config, if it is set
+#[derive(Debug, Clone)]
+pub struct BaseUrl(pub Option<String>);
+
+struct Feed {
+    url: String,
+    title: String,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    url: String,
+    title: String,
+    author: String,
+    updated: i64,
+    content: String,
+}
+
+impl Entry {
+    fn from_post(post: &Post, base: &str) -> Self {
+        let thread = if post.resto == 0 { post.no } else { post.resto };
+        let title = post
+            .sub
+            .as_deref()
+            .map(|sub| html_escape::decode_html_entities(sub).into_owned())
+            .or_else(|| {
+                let text = Comment::parse(post.com.as_deref()?).plain_text();
+                let line = text.lines().find(|line| !line.trim().is_empty())?.trim();
+                Some(match line.char_indices().nth(TITLE_LENGTH) {
+                    Some((end, _)) => format!("{}…", &line[..end]),
+                    None => line.to_string(),
+                })
+            })
+            .unwrap_or_else(|| format!("No.{}", post.no));
+        let author = match &post.trip {
+            Some(trip) => format!("{} {}", post.name, trip),
+            None => post.name.clone(),
+        };
+
+        let mut content = String::new();
+        if post.tim.is_some() && post.filedeleted == 0 && post.media_removed == 0 {
+            let _ = write!(
+                content,
+                r#"<p><a href="/cdn/{0}/{1}{2}"><img src="/cdn/{0}/{1}s.jpg"></a></p>"#,
+                encode_double_quoted_attribute(&post.board),
+                post.tim.unwrap_or_default(),
+                encode_double_quoted_attribute(post.ext.as_deref().unwrap_or_default()),
+            );
+        }
+        if let Some(com) = &post.com {
+            content.push_str(&comment_html(com, &post.board, Some(thread)));
+        }
+
+        Entry {
+            url: format!("{}/{}/thread/{}#p{}", base, post.board, thread, post.no),
+            title,
+            author: html_escape::decode_html_entities(&author).into_owned(),
+            updated: post.time,
+            content,
+        }
+    }
+}
+
+/// New threads on a board
+pub async fn get_board_feed(
+    extract::Path(board): extract::Path<String>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(base_url): extract::Extension<BaseUrl>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let base = site_url(&base_url, &headers);
+    let threads = query_as!(
+        Post,
+        r#"SELECT * FROM posts WHERE resto = 0 AND hidden = 0 AND board = ? ORDER BY no DESC LIMIT ?"#,
+        board,
+        FEED_ENTRIES
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    atom_response(&Feed {
+        url: format!("{}/{}", base, board),
+        title: format!("/{}/ - new threads", board),
+        entries: threads
+            .iter()
+            .map(|post| Entry::from_post(post, &base))
+            .collect(),
+    })
+}
+
+/// New replies in a thread
+pub async fn get_thread_feed(
+    extract::Path((board, no)): extract::Path<(String, i64)>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(base_url): extract::Extension<BaseUrl>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let base = site_url(&base_url, &headers);
+    let op = query_as!(
+        Post,
+        r#"SELECT * FROM posts WHERE resto = 0 AND hidden = 0 AND board = ? AND no = ?"#,
+        board,
+        no
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(any_error)?;
+    let posts = query_as!(
+        Post,
+        r#"SELECT * FROM posts WHERE board = ? AND (no = ? OR resto = ?) AND hidden = 0 ORDER BY no DESC LIMIT ?"#,
+        board,
+        no,
+        no,
+        FEED_ENTRIES
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    atom_response(&Feed {
+        url: format!("{}/{}/thread/{}", base, board, no),
+        title: format!("/{}/ - {}", board, Entry::from_post(&op, &base).title),
+        entries: posts
+            .iter()
+            .map(|post| Entry::from_post(post, &base))
+            .collect(),
+    })
+}
+
+/// Newest posts matching a full text search
+pub async fn get_search_feed(
+    extract::Query(query): extract::Query<SearchQuery>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    viewer: Viewer,
+    extract::Extension(base_url): extract::Extension<BaseUrl>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(AppError::Status(StatusCode::BAD_REQUEST));
+    }
+
+    let base = site_url(&base_url, &headers);
+    let posts = search_posts(&pool, &viewer, q, query.board(), FEED_ENTRIES, 0)
+        .await
+        .map_err(any_error)?;
+
+    let mut params = vec![("q", q)];
+    let mut title = format!("Search for \"{}\"", q);
+    if let Some(board) = query.board() {
+        params.push(("board", board));
+        let _ = write!(title, " on /{}/", board);
+    }
+    let params = serde_urlencoded::to_string(&params).map_err(any_error)?;
+
+    atom_response(&Feed {
+        url: format!("{}/search?{}", base, params),
+        title,
+        entries: posts
+            .iter()
+            .map(|post| Entry::from_post(post, &base))
+            .collect(),
+    })
+}
+
+/// URL the site is reached at, used to build the absolute links feeds require. Guessed
+/// from the request unless configured.
+fn site_url(base_url: &BaseUrl, headers: &HeaderMap) -> String {
+    if let BaseUrl(Some(base_url)) = base_url {
+        return base_url.clone();
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+        .filter(|proto| *proto == "https")
+        .unwrap_or("http");
+
+    format!("{}://{}", scheme, host)
+}
+
+fn atom_response(feed: &Feed) -> Result<Response, AppError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/atom+xml; charset=utf-8"),
+    );
+
+    Ok((headers, render_atom(feed)).into_response())
+}
+
+fn rfc3339(time: i64) -> String {
+    Utc.timestamp_opt(time, 0)
+        .single()
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn render_atom(feed: &Feed) -> String {
+    // feeds without entries were last updated whenever they're requested
+    let updated = feed
+        .entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or_else(|| Utc::now().timestamp());
+
+    let mut xml = String::new();
+    let _ = write!(
+        xml,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:base="{url}">
+<id>{url}</id>
+<title>{title}</title>
+<updated>{updated}</updated>
+<link rel="alternate" href="{url}"/>
+"#,
+        url = encode_double_quoted_attribute(&feed.url),
+        title = encode_text(&feed.title),
+        updated = rfc3339(updated),
+    );
+    for entry in &feed.entries {
+        let _ = write!(
+            xml,
+            r#"<entry>
+<id>{url}</id>
+<title>{title}</title>
+<updated>{updated}</updated>
+<author><name>{author}</name></author>
+<link rel="alternate" href="{url}"/>
+<content type="html">{content}</content>
+</entry>
+"#,
+            url = encode_double_quoted_attribute(&entry.url),
+            title = encode_text(&entry.title),
+            updated = rfc3339(entry.updated),
+            author = encode_text(&entry.author),
+            content = encode_text(&entry.content),
+        );
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[test]
+fn test_render_atom() {
+    let xml = render_atom(&Feed {
+        url: "http://localhost/g".to_string(),
+        title: "/g/ - new threads".to_string(),
+        entries: vec![Entry {
+            url: "http://localhost/g/thread/1#p1".to_string(),
+            title: "<b> & \"quotes\"".to_string(),
+            author: "Anonymous".to_string(),
+            updated: 1_650_000_000,
+            content: r#"<a href="/g">&gt;&gt;1</a>"#.to_string(),
+        }],
+    });
+
+    assert!(xml.contains("<updated>2022-04-15T05:20:00Z</updated>"));
+    assert!(xml.contains("<title>&lt;b&gt; &amp; \"quotes\"</title>"));
+    assert!(xml.contains(
+        r#"<content type="html">&lt;a href="/g"&gt;&amp;gt;&amp;gt;1&lt;/a&gt;</content>"#
+    ));
+}
@@ -0,0 +1,201 @@
+use axum::{extract, response::Html, Json};
+use fourchan::Post;
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::{
+    auth::Viewer,
+    config::PageSizes,
+    error::{any_error, AppError},
+    util::page_offset,
+    Pagination,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FilenameQuery {
+    #[serde(default)]
+    filename: String,
+    page: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageResults {
+    pub md5: String,
+    pub total: i64,
+    pub page: i64,
+    pub posts: Vec<Post>,
+}
+
+/// Lists every post with the attachment hash `md5`
+pub async fn get_image(
+    extract::Path(md5): extract::Path<String>,
+    extract::Query(pagination): extract::Query<Pagination>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
+    extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    let results = posts_by_md5(
+        &pool,
+        &viewer,
+        &md5,
+        pagination.page,
+        pages.results_per_page,
+    )
+    .await?;
+
+    let mut context = Context::new();
+    context.insert("md5", &results.md5);
+    context.insert("filename", "");
+    context.insert("total", &results.total);
+    context.insert("page", &results.page);
+    context.insert("pages", &pages.result_pages(results.total));
+    context.insert("results", &results.posts);
+    context.insert("poster_id", &None::<String>);
+
+    Ok(Html(t.render("image.html", &context).map_err(any_error)?))
+}
+
+pub async fn get_image_json(
+    extract::Path(md5): extract::Path<String>,
+    extract::Query(pagination): extract::Query<Pagination>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
+    viewer: Viewer,
+) -> Result<Json<ImageResults>, AppError> {
+    Ok(Json(
+        posts_by_md5(
+            &pool,
+            &viewer,
+            &md5,
+            pagination.page,
+            pages.results_per_page,
+        )
+        .await?,
+    ))
+}
+
+/// Searches attachments by their original file name
+pub async fn search_images(
+    extract::Query(query): extract::Query<FilenameQuery>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
+    extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    let private = viewer.can_read_private();
+    let filename = query.filename.trim();
+    let (page, offset) = page_offset(query.page, pages.results_per_page)?;
+
+    let (results, total) = if filename.is_empty() {
+        (Vec::new(), 0)
+    } else {
+        let pattern = like_pattern(filename);
+        let results = query_as!(
+            Post,
+            r#"
+            SELECT * FROM posts WHERE filename LIKE ? ESCAPE '\' AND hidden = 0
+                AND (? OR board NOT IN private_boards)
+            ORDER BY time DESC, no DESC LIMIT ? OFFSET ?
+            "#,
+            pattern,
+            private,
+            pages.results_per_page,
+            offset
+        )
+        .fetch_all(&pool)
+        .await
+        .map_err(any_error)?;
+        let total = query_scalar!(
+            r#"
+            SELECT count(*) as "count!: i64" FROM posts WHERE filename LIKE ? ESCAPE '\' AND hidden = 0
+                AND (? OR board NOT IN private_boards)
+            "#,
+            pattern,
+            private
+        )
+        .fetch_one(&pool)
+        .await
+        .map_err(any_error)?;
+
+        (results, total)
+    };
+
+    let mut context = Context::new();
+    context.insert("md5", "");
+    context.insert("filename", filename);
+    context.insert("total", &total);
+    context.insert("page", &page);
+    context.insert("pages", &pages.result_pages(total));
+    context.insert("results", &results);
+    context.insert("poster_id", &None::<String>);
+
+    Ok(Html(t.render("image.html", &context).map_err(any_error)?))
+}
+
+async fn posts_by_md5(
+    pool: &SqlitePool,
+    viewer: &Viewer,
+    md5: &str,
+    page: Option<i64>,
+    per_page: i64,
+) -> Result<ImageResults, AppError> {
+    // 4chan hashes are standard base64, accept the url safe alphabet as well
+    let md5 = md5.replace('-', "+").replace('_', "/");
+    let (page, offset) = page_offset(page, per_page)?;
+    let private = viewer.can_read_private();
+
+    let posts = query_as!(
+        Post,
+        r#"
+        SELECT * FROM posts WHERE md5 = ? AND hidden = 0
+            AND (? OR board NOT IN private_boards)
+        ORDER BY time ASC, no ASC LIMIT ? OFFSET ?
+        "#,
+        md5,
+        private,
+        per_page,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(any_error)?;
+    let total = query_scalar!(
+        r#"
+        SELECT count(*) as "count!: i64" FROM posts WHERE md5 = ? AND hidden = 0
+            AND (? OR board NOT IN private_boards)
+        "#,
+        md5,
+        private
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(any_error)?;
+
+    Ok(ImageResults {
+        md5,
+        total,
+        page,
+        posts,
+    })
+}
+
+/// Builds a `LIKE` pattern matching values that contain `text`
+fn like_pattern(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len() + 2);
+    pattern.push('%');
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[test]
+fn test_like_pattern() {
+    assert_eq!(like_pattern("cat"), "%cat%");
+    assert_eq!(like_pattern("100%_a\\b"), "%100\\%\\_a\\\\b%");
+}
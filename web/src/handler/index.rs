@@ -2,14 +2,22 @@ use axum::{extract, response::Html};
 use sqlx::SqlitePool;
 use tera::{Tera, Context};
 
-use crate::{BoardListing, error::{AppError, any_error}};
+use crate::{BoardListing, auth::Viewer, error::{AppError, any_error}};
 
 pub async fn get_index(
     extract::Extension(pool): extract::Extension<SqlitePool>,
     extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
 ) -> Result<Html<String>, AppError> {
+    let private = viewer.can_read_private();
     let boards = query_as!(BoardListing,
-        r#"SELECT board, ifnull(count(no), 0) as thread_count FROM posts WHERE resto = 0 GROUP BY board"#,
+        r#"
+        SELECT board, ifnull(count(no), 0) as thread_count FROM posts
+        WHERE resto = 0 AND hidden = 0
+            AND (? OR board NOT IN private_boards)
+        GROUP BY board
+        "#,
+        private
     )
     .fetch_all(&pool)
     .await
@@ -17,6 +25,7 @@ pub async fn get_index(
 
     let mut context = Context::new();
     context.insert("boards", &boards);
+    context.insert("user", &viewer.user);
 
     Ok(Html(t.render("index.html", &context).map_err(any_error)?))
 }
@@ -0,0 +1,157 @@
+use axum::{
+    extract,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use chrono::Utc;
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::{
+    auth::{self, Viewer, SESSION_COOKIE, SESSION_SECONDS},
+    error::{any_error, AppError},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    username: String,
+    password: String,
+    next: Option<String>,
+}
+
+pub async fn get_login(
+    extract::Query(query): extract::Query<LoginQuery>,
+    extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    render_login(&t, &viewer, query.next.as_deref(), None)
+}
+
+/// Signs in with a username and password and starts a session
+pub async fn post_login(
+    extract::Form(form): extract::Form<LoginForm>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let user = query!(
+        "SELECT id, password_hash FROM users WHERE username = ?",
+        form.username
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(any_error)?;
+    let user_id = match user {
+        Some(user) if auth::verify_password(&form.password, &user.password_hash) => user.id,
+        _ => {
+            let page = render_login(
+                &t,
+                &viewer,
+                form.next.as_deref(),
+                Some("Wrong username or password"),
+            )?;
+            return Ok((StatusCode::UNAUTHORIZED, page).into_response());
+        }
+    };
+
+    let token = auth::generate_token().map_err(any_error)?;
+    let token_hash = auth::hash_token(&token);
+    let now = Utc::now().timestamp();
+    let expires_at = now + SESSION_SECONDS;
+    query!("DELETE FROM sessions WHERE expires_at <= ?", now)
+        .execute(&pool)
+        .await
+        .map_err(any_error)?;
+    query!(
+        r#"
+        INSERT INTO sessions (token_hash, user_id, created_at, expires_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+        token_hash,
+        user_id,
+        now,
+        expires_at
+    )
+    .execute(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let secure = headers
+        .get("x-forwarded-proto")
+        .map_or(false, |proto| proto == "https");
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        SESSION_COOKIE,
+        token,
+        SESSION_SECONDS,
+        if secure { "; Secure" } else { "" }
+    );
+    let cookie = HeaderValue::from_str(&cookie).map_err(any_error)?;
+
+    let next = form
+        .next
+        .as_deref()
+        .filter(|next| is_local(next))
+        .unwrap_or("/");
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(next)).into_response())
+}
+
+/// Ends the current session
+pub async fn post_logout(
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if let Some(session) = auth::session_token(&headers) {
+        let token_hash = auth::hash_token(session);
+        query!("DELETE FROM sessions WHERE token_hash = ?", token_hash)
+            .execute(&pool)
+            .await
+            .map_err(any_error)?;
+    }
+
+    let cookie = HeaderValue::from_str(&format!(
+        "{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax",
+        SESSION_COOKIE
+    ))
+    .map_err(any_error)?;
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response())
+}
+
+fn render_login(
+    t: &Tera,
+    viewer: &Viewer,
+    next: Option<&str>,
+    error: Option<&str>,
+) -> Result<Html<String>, AppError> {
+    let mut context = Context::new();
+    context.insert("user", &viewer.user);
+    context.insert("next", &next.filter(|next| is_local(next)));
+    context.insert("error", &error);
+
+    Ok(Html(t.render("login.html", &context).map_err(any_error)?))
+}
+
+/// Only redirect to paths on this site after signing in. The path also has to be a valid
+/// header value, `Redirect::to` panics otherwise.
+fn is_local(next: &str) -> bool {
+    next.starts_with('/')
+        && !next.starts_with("//")
+        && !next.starts_with("/\\")
+        && HeaderValue::from_str(next).is_ok()
+}
+
+#[test]
+fn test_is_local() {
+    assert!(is_local("/g/thread/1"));
+    assert!(!is_local("//example.com"));
+    assert!(!is_local("/\\example.com"));
+    assert!(!is_local("https://example.com"));
+    assert!(!is_local("/\n"));
+    assert!(!is_local("/g/\r\nSet-Cookie: x=y"));
+}
@@ -2,8 +2,28 @@ pub mod thread;
 pub mod board;
 pub mod index;
 pub mod cdn;
+pub mod api;
+pub mod catalog;
+pub mod trip;
+pub mod image;
+pub mod similar;
+pub mod search;
+pub mod feed;
+pub mod stats;
+pub mod admin;
+pub mod login;
 
 pub use thread::*;
 pub use board::*;
 pub use index::*;
 pub use cdn::*;
+pub use api::*;
+pub use catalog::*;
+pub use trip::*;
+pub use image::*;
+pub use similar::*;
+pub use search::*;
+pub use feed::*;
+pub use stats::*;
+pub use admin::*;
+pub use login::*;
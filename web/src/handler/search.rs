@@ -0,0 +1,136 @@
+use axum::{extract, response::Html};
+use fourchan::Post;
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::{
+    auth::Viewer,
+    config::PageSizes,
+    error::{any_error, AppError},
+    util::page_offset,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub q: String,
+    pub board: Option<String>,
+    pub page: Option<i64>,
+}
+
+impl SearchQuery {
+    /// Board the search is restricted to, if any
+    pub fn board(&self) -> Option<&str> {
+        self.board.as_deref().filter(|board| !board.is_empty())
+    }
+}
+
+/// Searches subjects and comments, newest first
+pub async fn get_search(
+    extract::Query(query): extract::Query<SearchQuery>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    let (page, offset) = page_offset(query.page, pages.results_per_page)?;
+    let (results, total) = if query.q.trim().is_empty() {
+        (Vec::new(), 0)
+    } else {
+        let results = search_posts(
+            &pool,
+            &viewer,
+            &query.q,
+            query.board(),
+            pages.results_per_page,
+            offset,
+        )
+        .await
+        .map_err(any_error)?;
+        let total = count_posts(&pool, &viewer, &query.q, query.board())
+            .await
+            .map_err(any_error)?;
+        (results, total)
+    };
+
+    let mut context = Context::new();
+    context.insert("q", query.q.trim());
+    context.insert("search_board", &query.board());
+    context.insert("results", &results);
+    context.insert("total", &total);
+    context.insert("page", &page);
+    context.insert("pages", &pages.result_pages(total));
+    context.insert("poster_id", &None::<String>);
+
+    Ok(Html(t.render("search.html", &context).map_err(any_error)?))
+}
+
+pub async fn search_posts(
+    pool: &SqlitePool,
+    viewer: &Viewer,
+    q: &str,
+    board: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> sqlx::Result<Vec<Post>> {
+    let q = fts_query(q);
+    let private = viewer.can_read_private();
+    query_as!(
+        Post,
+        r#"
+        SELECT posts.* FROM posts_fts
+        JOIN posts ON posts.rowid = posts_fts.rowid
+        WHERE posts_fts MATCH ? AND posts.hidden = 0 AND (? IS NULL OR posts.board = ?)
+            AND (? OR posts.board NOT IN private_boards)
+        ORDER BY posts.time DESC, posts.no DESC
+        LIMIT ? OFFSET ?
+        "#,
+        q,
+        board,
+        board,
+        private,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn count_posts(
+    pool: &SqlitePool,
+    viewer: &Viewer,
+    q: &str,
+    board: Option<&str>,
+) -> sqlx::Result<i64> {
+    let q = fts_query(q);
+    let private = viewer.can_read_private();
+    query_scalar!(
+        r#"
+        SELECT count(*) as "count!: i64" FROM posts_fts
+        JOIN posts ON posts.rowid = posts_fts.rowid
+        WHERE posts_fts MATCH ? AND posts.hidden = 0 AND (? IS NULL OR posts.board = ?)
+            AND (? OR posts.board NOT IN private_boards)
+        "#,
+        q,
+        board,
+        board,
+        private
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Turns user input into an FTS5 query matching posts that contain all of the words.
+/// Every word is quoted, so FTS5 operators in the input are searched for literally.
+fn fts_query(q: &str) -> String {
+    q.split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn test_fts_query() {
+    assert_eq!(fts_query("  hello world "), r#""hello" "world""#);
+    assert_eq!(fts_query(r#"a"b OR -c*"#), r#""a""b" "OR" "-c*""#);
+}
@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+
+use axum::{
+    body::Bytes,
+    extract::{self, ContentLengthLimit},
+    response::Html,
+    Json,
+};
+use fourchan::Post;
+use http::StatusCode;
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::{
+    auth::Viewer,
+    error::{any_error, AppError},
+};
+
+/// Largest image that can be uploaded to search for
+const MAX_UPLOAD_SIZE: u64 = 8 * 1024 * 1024;
+
+const DEFAULT_DISTANCE: u32 = 7;
+const MAX_MATCHES: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarQuery {
+    distance: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarImage {
+    pub distance: u32,
+    pub post: Post,
+}
+
+pub async fn get_similar_images_form(
+    extract::Extension(t): extract::Extension<Tera>,
+) -> Result<Html<String>, AppError> {
+    let mut context = Context::new();
+    context.insert("default_distance", &DEFAULT_DISTANCE);
+    context.insert("max_distance", &arkiv_phash::MAX_DISTANCE);
+
+    Ok(Html(t.render("similar.html", &context).map_err(any_error)?))
+}
+
+/// Finds posts with images similar to the uploaded one, closest first
+pub async fn find_similar_images(
+    extract::Query(query): extract::Query<SimilarQuery>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    viewer: Viewer,
+    ContentLengthLimit(body): ContentLengthLimit<Bytes, MAX_UPLOAD_SIZE>,
+) -> Result<Json<Vec<SimilarImage>>, AppError> {
+    let hash = tokio::task::spawn_blocking(move || arkiv_phash::dhash_bytes(&body))
+        .await
+        .map_err(any_error)?
+        .map_err(|_| AppError::Status(StatusCode::UNPROCESSABLE_ENTITY))?;
+    let max_distance = query
+        .distance
+        .unwrap_or(DEFAULT_DISTANCE)
+        .min(arkiv_phash::MAX_DISTANCE);
+
+    let radius = arkiv_phash::chunk_radius(max_distance);
+    let [chunk0, chunk1, chunk2, chunk3] = arkiv_phash::chunks(hash).map(|chunk| {
+        let neighbors: Vec<String> = arkiv_phash::chunk_neighbors(chunk, radius)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        format!("[{}]", neighbors.join(","))
+    });
+    let candidates = query!(
+        r#"
+        SELECT board, tim, hash FROM image_hashes
+        WHERE chunk0 IN (SELECT value FROM json_each(?))
+            OR chunk1 IN (SELECT value FROM json_each(?))
+            OR chunk2 IN (SELECT value FROM json_each(?))
+            OR chunk3 IN (SELECT value FROM json_each(?))
+        "#,
+        chunk0,
+        chunk1,
+        chunk2,
+        chunk3
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let mut matches: Vec<_> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let distance =
+                arkiv_phash::distance(hash, u64::from_be_bytes(candidate.hash.to_be_bytes()));
+            (distance <= max_distance).then_some((distance, candidate.board, candidate.tim))
+        })
+        .collect();
+    matches.sort_unstable();
+    matches.truncate(MAX_MATCHES);
+
+    let private = viewer.can_read_private();
+    let mut similar = Vec::new();
+    let mut seen = HashSet::new();
+    for (distance, board, tim) in matches {
+        let posts = query_as!(
+            Post,
+            r#"
+            SELECT * FROM posts WHERE board = ? AND tim = ? AND hidden = 0
+                AND (? OR board NOT IN private_boards)
+            "#,
+            board,
+            tim,
+            private
+        )
+        .fetch_all(&pool)
+        .await
+        .map_err(any_error)?;
+        for post in posts {
+            if seen.insert((post.board.clone(), post.no)) {
+                similar.push(SimilarImage { distance, post });
+            }
+        }
+    }
+
+    Ok(Json(similar))
+}
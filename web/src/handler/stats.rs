@@ -0,0 +1,166 @@
+use axum::{extract, response::Html};
+use http::StatusCode;
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::error::{any_error, AppError};
+
+/// Number of rows in the top lists
+const TOP_ENTRIES: i64 = 20;
+
+#[derive(Debug, Serialize)]
+struct Activity {
+    time: i64,
+    posts: i64,
+    threads: i64,
+    images: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Ranked {
+    key: String,
+    name: Option<String>,
+    posts: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct TripActivity {
+    trip: String,
+    posts: i64,
+    first_post: i64,
+    last_post: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct MediaType {
+    ext: String,
+    files: i64,
+    bytes: i64,
+}
+
+/// Statistics about a board, read from the rollups the archiver keeps up to date
+#[allow(clippy::too_many_lines)]
+pub async fn get_stats(
+    extract::Path(board): extract::Path<String>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+) -> Result<Html<String>, AppError> {
+    let summary = query!(
+        "SELECT posts, min_no, max_no, refreshed_at FROM stats_boards WHERE board = ?",
+        board
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(any_error)?
+    .ok_or(AppError::Status(StatusCode::NOT_FOUND))?;
+
+    let hourly = query_as!(
+        Activity,
+        r#"
+        SELECT hour as time, posts, threads, images FROM stats_hourly
+        WHERE board = ? ORDER BY hour DESC LIMIT 48
+        "#,
+        board
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+    let daily = query_as!(
+        Activity,
+        r#"
+        SELECT hour / 86400 * 86400 as "time!: i64", sum(posts) as "posts!: i64",
+            sum(threads) as "threads!: i64", sum(images) as "images!: i64"
+        FROM stats_hourly WHERE board = ?
+        GROUP BY hour / 86400 ORDER BY hour / 86400 DESC LIMIT 30
+        "#,
+        board
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let countries = query_as!(
+        Ranked,
+        r#"
+        SELECT country as key, country_name as name, posts FROM stats_countries
+        WHERE board = ? ORDER BY posts DESC LIMIT ?
+        "#,
+        board,
+        TOP_ENTRIES
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+    let flags = query_as!(
+        Ranked,
+        r#"
+        SELECT flag as key, flag_name as name, posts FROM stats_flags
+        WHERE board = ? ORDER BY posts DESC LIMIT ?
+        "#,
+        board,
+        TOP_ENTRIES
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+    let trips = query_as!(
+        TripActivity,
+        r#"
+        SELECT trip, posts, first_post, last_post FROM stats_trips
+        WHERE board = ? ORDER BY posts DESC LIMIT ?
+        "#,
+        board,
+        TOP_ENTRIES
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+    let media = query_as!(
+        MediaType,
+        "SELECT ext, files, bytes FROM stats_media WHERE board = ? ORDER BY files DESC",
+        board
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+
+    let threads = query!(
+        r#"
+        SELECT count(*) as "threads!: i64", avg(last_post - created) as "lifespan: f64"
+        FROM stats_threads WHERE board = ?
+        "#,
+        board
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(any_error)?;
+    let skipped_media = query_scalar!(
+        r#"SELECT count(*) as "count!: i64" FROM skipped_media WHERE board = ?"#,
+        board
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(any_error)?;
+
+    // post numbers are assigned per board, so gaps are posts that were never archived
+    let numbered = summary.max_no - summary.min_no + 1;
+    #[allow(clippy::cast_precision_loss)]
+    let coverage = summary.posts as f64 / numbered as f64;
+
+    let mut context = Context::new();
+    context.insert("board", &board);
+    context.insert("posts", &summary.posts);
+    context.insert("refreshed_at", &summary.refreshed_at);
+    context.insert("coverage", &coverage);
+    context.insert("hourly", &hourly);
+    context.insert("daily", &daily);
+    context.insert("countries", &countries);
+    context.insert("flags", &flags);
+    context.insert("trips", &trips);
+    context.insert("media", &media);
+    context.insert("threads", &threads.threads);
+    context.insert("avg_lifespan", &threads.lifespan);
+    context.insert("skipped_media", &skipped_media);
+
+    Ok(Html(t.render("stats.html", &context).map_err(any_error)?))
+}
@@ -1,34 +1,134 @@
-use crate::error::{any_error, AppError};
+use std::collections::HashMap;
+
+use crate::{
+    auth::Viewer,
+    error::{any_error, AppError},
+};
 use axum::{extract, response::Html};
 use fourchan::Post;
 use http::StatusCode;
 use tera::Tera;
 
+/// A post quoting another post
+#[derive(Debug, Serialize)]
+pub struct Backlink {
+    pub board: String,
+    pub thread: i64,
+    pub no: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct ThreadPost {
+    #[serde(flatten)]
+    post: Post,
+    backlinks: Vec<Backlink>,
+
+    /// Number of posts in the thread with the same poster ID
+    id_posts: Option<usize>,
+}
+
 pub async fn get_thread(
     extract::Path((board, id)): extract::Path<(String, i64)>,
     extract::Extension(pool): extract::Extension<sqlx::SqlitePool>,
     extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    render_thread(&pool, &t, &viewer, board, id, None).await
+}
+
+/// Shows a thread with the posts of one poster ID highlighted
+pub async fn get_thread_poster(
+    extract::Path((board, id, poster_id)): extract::Path<(String, i64, String)>,
+    extract::Extension(pool): extract::Extension<sqlx::SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    render_thread(&pool, &t, &viewer, board, id, Some(poster_id)).await
+}
+
+async fn render_thread(
+    pool: &sqlx::SqlitePool,
+    t: &Tera,
+    viewer: &Viewer,
+    board: String,
+    id: i64,
+    poster_id: Option<String>,
 ) -> Result<Html<String>, AppError> {
     let posts = query_as!(
         Post,
         r#"
-        SELECT * FROM posts WHERE board = ? AND (no = ? OR resto = ?)
+        SELECT * FROM posts WHERE board = ? AND (no = ? OR resto = ?) AND hidden = 0
         "#,
         board,
         id,
         id
     )
-    .fetch_all(&pool)
+    .fetch_all(pool)
     .await
     .map_err(any_error)?;
 
-    if posts.is_empty() {
+    // replies can outlive the opening post, e.g. when only the thread was hidden
+    if !posts.iter().any(|post| post.no == id && post.resto == 0) {
         return Err(AppError::Status(StatusCode::NOT_FOUND));
     }
+    if let Some(poster_id) = &poster_id {
+        if !posts.iter().any(|post| post.id.as_ref() == Some(poster_id)) {
+            return Err(AppError::Status(StatusCode::NOT_FOUND));
+        }
+    }
+
+    let private = viewer.can_read_private();
+    let links = query!(
+        r#"
+        SELECT board, no, thread, target_post FROM post_links
+        WHERE target_board = ? AND target_post IN
+            (SELECT no FROM posts WHERE board = ? AND (no = ? OR resto = ?))
+            AND NOT EXISTS (SELECT 1 FROM posts p
+                WHERE p.board = post_links.board AND p.no = post_links.no AND p.hidden = 1)
+            AND (? OR post_links.board NOT IN private_boards)
+        ORDER BY board, no
+        "#,
+        board,
+        board,
+        id,
+        id,
+        private
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(any_error)?;
+
+    let mut backlinks: HashMap<i64, Vec<Backlink>> = HashMap::new();
+    for link in links {
+        backlinks
+            .entry(link.target_post)
+            .or_default()
+            .push(Backlink {
+                board: link.board,
+                thread: link.thread,
+                no: link.no,
+            });
+    }
+    let mut id_posts: HashMap<String, usize> = HashMap::new();
+    for post in &posts {
+        if let Some(id) = &post.id {
+            *id_posts.entry(id.clone()).or_default() += 1;
+        }
+    }
+    let posts: Vec<ThreadPost> = posts
+        .into_iter()
+        .map(|post| ThreadPost {
+            backlinks: backlinks.remove(&post.no).unwrap_or_default(),
+            id_posts: post.id.as_ref().and_then(|id| id_posts.get(id).copied()),
+            post,
+        })
+        .collect();
 
     let mut context = tera::Context::new();
     context.insert("board", &board);
+    context.insert("thread", &id);
     context.insert("posts", &posts);
+    context.insert("poster_id", &poster_id);
 
     Ok(Html(t.render("thread.html", &context).map_err(any_error)?))
 }
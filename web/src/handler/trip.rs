@@ -0,0 +1,66 @@
+use axum::{extract, response::Html};
+use fourchan::Post;
+use sqlx::SqlitePool;
+use tera::{Context, Tera};
+
+use crate::{
+    auth::Viewer,
+    config::PageSizes,
+    error::{any_error, AppError},
+    util::page_offset,
+    Pagination,
+};
+
+/// Lists the posts signed with a tripcode on all boards, newest first
+pub async fn get_trip(
+    extract::Path(trip): extract::Path<String>,
+    extract::Query(pagination): extract::Query<Pagination>,
+    extract::Extension(pool): extract::Extension<SqlitePool>,
+    extract::Extension(t): extract::Extension<Tera>,
+    extract::Extension(pages): extract::Extension<PageSizes>,
+    viewer: Viewer,
+) -> Result<Html<String>, AppError> {
+    let (page, offset) = page_offset(pagination.page, pages.results_per_page)?;
+    let private = viewer.can_read_private();
+
+    let results = query_as!(
+        Post,
+        r#"
+        SELECT * FROM posts WHERE trip = ? AND hidden = 0
+            AND (? OR board NOT IN private_boards)
+        ORDER BY time DESC, no DESC LIMIT ? OFFSET ?
+        "#,
+        trip,
+        private,
+        pages.results_per_page,
+        offset
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(any_error)?;
+    let total = query_scalar!(
+        r#"
+        SELECT count(*) as "count!: i64" FROM posts WHERE trip = ? AND hidden = 0
+            AND (? OR board NOT IN private_boards)
+        "#,
+        trip,
+        private
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(any_error)?;
+
+    if total == 0 {
+        return Err(AppError::Status(http::StatusCode::NOT_FOUND));
+    }
+
+    let mut context = Context::new();
+    context.insert("trip", &trip);
+    context.insert("results", &results);
+    context.insert("total", &total);
+    context.insert("page", &page);
+    context.insert("pages", &pages.result_pages(total));
+    context.insert("poster_id", &None::<String>);
+
+    Ok(Html(t.render("trip.html", &context).map_err(any_error)?))
+}
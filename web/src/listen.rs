@@ -0,0 +1,96 @@
+//! Serving the app on TCP addresses and a Unix socket
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Context as AnyhowContext;
+use axum::{extract::connect_info::Connected, Router, Server};
+use futures::future::{try_join_all, BoxFuture};
+use hyper::server::{accept::Accept, conn::AddrStream};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::info;
+
+/// Address of the other end of a connection. Connections to the Unix socket don't have one.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub Option<IpAddr>);
+
+impl Connected<&AddrStream> for PeerAddr {
+    fn connect_info(target: &AddrStream) -> Self {
+        PeerAddr(Some(target.remote_addr().ip()))
+    }
+}
+
+impl Connected<&UnixStream> for PeerAddr {
+    fn connect_info(_target: &UnixStream) -> Self {
+        PeerAddr(None)
+    }
+}
+
+struct UnixAccept(UnixListener);
+
+impl Accept for UnixAccept {
+    type Conn = UnixStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.0
+            .poll_accept(cx)
+            .map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+    }
+}
+
+/// Serves `app` on every address and the Unix socket until one of them fails
+pub async fn serve(
+    app: Router,
+    addrs: &[SocketAddr],
+    unix_socket: Option<&Path>,
+) -> anyhow::Result<()> {
+    let mut servers: Vec<BoxFuture<anyhow::Result<()>>> = Vec::new();
+
+    for addr in addrs {
+        let server = Server::try_bind(addr)
+            .with_context(|| format!("failed to listen on {}", addr))?
+            .serve(
+                app.clone()
+                    .into_make_service_with_connect_info::<PeerAddr>(),
+            );
+        info!("listening on {}", addr);
+        servers.push(Box::pin(
+            async move { server.await.context("server failed") },
+        ));
+    }
+
+    if let Some(path) = unix_socket {
+        let listener = bind_unix(path)?;
+        let server = Server::builder(UnixAccept(listener))
+            .serve(app.into_make_service_with_connect_info::<PeerAddr>());
+        info!("listening on {}", path.display());
+        servers.push(Box::pin(
+            async move { server.await.context("server failed") },
+        ));
+    }
+
+    try_join_all(servers).await?;
+    Ok(())
+}
+
+/// Binds the Unix socket, replacing a socket left behind by an earlier run
+fn bind_unix(path: &Path) -> anyhow::Result<UnixListener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    UnixListener::bind(path).with_context(|| format!("failed to listen on {}", path.display()))
+}
@@ -3,24 +3,73 @@
 #![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
 
 use anyhow::Context as AnyhowContext;
-use axum::{extract::Extension, routing::get, Router, Server};
-use http::StatusCode;
-use std::net::SocketAddr;
-use tera::Tera;
-use tracing::{error, info};
+use axum::{
+    extract::Extension,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use clap::{Parser, Subcommand};
+use config::{Config, StorageConfig};
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+use std::{path::PathBuf, str::FromStr};
+use tracing::info;
 
-use crate::{handler::{get_index, get_board, get_thread, cdn}, util::html_decode};
+use crate::{
+    assets::Assets,
+    handler::{
+        cdn, find_similar_images, get_admin, get_admin_post, get_board, get_board_feed,
+        get_catalog, get_image, get_image_json, get_index, get_login, get_reply_tree, get_search,
+        get_search_feed, get_similar_images_form, get_stats, get_thread, get_thread_feed,
+        get_thread_poster, get_trip, post_admin_action, post_blocklist, post_login, post_logout,
+        search_images, BaseUrl,
+    },
+};
 
 #[macro_use]
 extern crate sqlx;
 #[macro_use]
 extern crate serde;
 
+mod assets;
+mod auth;
+mod cli;
+mod config;
+mod db;
 mod error;
 mod handler;
+mod listen;
+mod ratelimit;
 mod util;
 
-const THREADS_PER_PAGE: i32 = 40;
+#[derive(Debug, Parser)]
+#[clap(version, about)]
+struct Cli {
+    /// Config file. Default: `WEB_CONFIG_PATH` or web.yml
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Don't apply database migrations at startup. The database has to be up to date
+    #[clap(long, global = true)]
+    no_migrate: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the archive. This is the default
+    Serve,
+    /// Manage the users that can sign in
+    #[clap(subcommand)]
+    User(cli::UserCommand),
+    /// Manage API tokens
+    #[clap(subcommand)]
+    Token(cli::TokenCommand),
+    /// Show or change who can see a board
+    Visibility(cli::VisibilityArgs),
+}
 
 #[derive(Debug, Serialize)]
 struct BoardListing {
@@ -32,55 +81,146 @@ struct BoardListing {
 pub struct Pagination {
     before: Option<i64>,
     after: Option<i64>,
+    page: Option<i64>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    dotenv::dotenv().unwrap();
+    let cli = Cli::parse();
+    // the environment may be configured without a .env file
+    dotenv::dotenv().ok();
     tracing_subscriber::fmt::init();
-    let mut t = Tera::new("web/templates/**/*.html").context("failed to compile templates")?;
-    t.register_filter("html_decode", html_decode);
-    info!("templates done");
-
-    let data_dir = std::env::var_os("DATA_DIR").context("missing data dir var")?;
-    let storage = arkiv_storage::local::LocalStorage::new(&data_dir);
 
+    let config = Config::load(cli.config.as_deref())?;
+    let database_url = config
+        .database_url
+        .as_deref()
+        .context("database_url is not set, use the config file or DATABASE_URL")?;
+    // a fresh deployment starts with an empty database
+    let options = SqliteConnectOptions::from_str(database_url)
+        .context("invalid database_url")?
+        .create_if_missing(!cli.no_migrate);
     let pool = sqlx::sqlite::SqlitePoolOptions::new()
         .max_connections(10)
-        .connect(&std::env::var("DATABASE_URL").context("missing db env var")?)
+        .connect_with(options)
         .await
         .context("failed to connect to database")?;
+    if !cli.no_migrate {
+        db::migrate(&pool).await?;
+    }
+    db::check_schema(&pool).await?;
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(pool, config).await,
+        Command::User(command) => cli::user(&pool, command).await,
+        Command::Token(command) => cli::token(&pool, command).await,
+        Command::Visibility(args) => cli::visibility(&pool, args).await,
+    }
+}
+
+async fn serve(pool: SqlitePool, mut config: Config) -> anyhow::Result<()> {
+    config.validate()?;
+    let features = config.features;
+
+    let template_assets = Assets::templates(config.templates_dir.as_deref(), config.dev);
+    let t = assets::templates(&template_assets, features)?;
+    info!("templates done");
+    let static_assets = Assets::static_files(config.static_dir.as_deref(), config.dev);
+
+    let storage = match &config.storage {
+        StorageConfig::Local { path } => arkiv_storage::local::LocalStorage::new(
+            path.as_ref().context("storage.path is not set")?,
+        ),
+    };
+    let rate_limiter = ratelimit::RateLimiter::new(config.rate_limit.parse()?);
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(get_index))
         .route("/:board", get(get_board))
-        .route(
-            "/:board/thread/:thread_id",
-            get(get_thread),
-        )
+        .route("/:board/catalog", get(get_catalog))
+        .route("/:board/thread/:thread_id", get(get_thread))
+        .route("/:board/thread/:thread_id/id/:id", get(get_thread_poster))
         .route(
             "/cdn/:board/:key",
             get(cdn::<arkiv_storage::local::LocalStorage>),
         )
-        .nest(
-            "/static",
-            axum::routing::get_service(tower_http::services::ServeDir::new("./static/"))
-                .handle_error(|err| async move {
-                    error!("error occured: {}", err);
-                    (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
-                }),
-        )
+        // same layout as i.4cdn.org, so rewritten 4chan media links resolve
+        .route(
+            "/i/:board/:key",
+            get(cdn::<arkiv_storage::local::LocalStorage>),
+        );
+    if features.feeds {
+        app = app
+            .route("/:board/feed.atom", get(get_board_feed))
+            .route("/:board/thread/:thread_id/feed.atom", get(get_thread_feed));
+    }
+    if features.stats {
+        app = app.route("/:board/stats", get(get_stats));
+    }
+    if features.search {
+        app = app
+            .route("/search", get(get_search))
+            .route("/trip/:trip", get(get_trip));
+        if features.feeds {
+            app = app.route("/search/feed.atom", get(get_search_feed));
+        }
+    }
+    if features.image_search {
+        app = app
+            .route("/image", get(search_images))
+            .route("/image/similar", get(get_similar_images_form))
+            .route("/image/:md5", get(get_image));
+        if features.api {
+            app = app
+                .route("/api/image/similar", post(find_similar_images))
+                .route("/api/image/:md5", get(get_image_json));
+        }
+    }
+    if features.api {
+        app = app.route("/api/:board/post/:no/replies", get(get_reply_tree));
+    }
+    if features.accounts {
+        app = app
+            .route("/login", get(get_login).post(post_login))
+            .route("/logout", post(post_logout));
+    }
+    if features.admin {
+        app = app
+            .route("/admin", get(get_admin))
+            .route(
+                "/admin/post",
+                get(get_admin_post).post(post_admin_action::<arkiv_storage::local::LocalStorage>),
+            )
+            .route("/admin/blocklist", post(post_blocklist));
+    }
+
+    let mut app = app
+        .route_layer(middleware::from_fn(auth::board_visibility))
+        .route("/static/*path", get(assets::get_static));
+    if config.dev {
+        info!("development mode, templates are reloaded when they change");
+        app = app
+            .layer(middleware::from_fn(assets::reload_templates))
+            .layer(Extension(assets::ReloadingTemplates::new(
+                template_assets,
+                features,
+                t.clone(),
+            )));
+    }
+
+    let app = app
+        .layer(middleware::from_fn(auth::identify))
+        .layer(middleware::from_fn(ratelimit::rate_limit))
         .layer(Extension(t.clone()))
+        .layer(Extension(static_assets))
         .layer(Extension(storage.clone()))
         .layer(Extension(pool.clone()))
+        .layer(Extension(config.admin.clone()))
+        .layer(Extension(config.pages))
+        .layer(Extension(features))
+        .layer(Extension(BaseUrl(config.base_url.clone())))
+        .layer(Extension(rate_limiter))
         .layer(tower_http::trace::TraceLayer::new_for_http());
 
-    let addr = "0.0.0.0:8080"
-        .parse::<SocketAddr>()
-        .context("failed to parse address")?;
-
-    info!("listening on {:?}", &addr);
-    Server::bind(&addr).serve(app.into_make_service()).await?;
-
-    Ok(())
+    listen::serve(app, &config.listen, config.unix_socket.as_deref()).await
 }
@@ -0,0 +1,358 @@
+//! Per-client rate limiting.
+//!
+//! Every client gets a token bucket for each [`Class`] of routes, so hammering the search
+//! doesn't use up the budget for browsing threads. Clients are identified by their IP
+//! address, IPv6 addresses by their /64 network. `X-Forwarded-For` is only believed when
+//! the request comes from one of the trusted proxies or through the Unix socket.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, HeaderMap, Request, StatusCode};
+
+use crate::listen::PeerAddr;
+
+/// How often buckets of clients that went quiet are dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Routes sharing a budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    Html,
+    Api,
+    Search,
+    Media,
+}
+
+impl Class {
+    fn of(path: &str) -> Self {
+        if path == "/search"
+            || path.starts_with("/search/")
+            || path == "/image"
+            || path.starts_with("/image/similar")
+            || path.starts_with("/api/image/similar")
+        {
+            Class::Search
+        } else if path.starts_with("/api/") {
+            Class::Api
+        } else if ["/cdn/", "/i/", "/static/"]
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+        {
+            Class::Media
+        } else {
+            Class::Html
+        }
+    }
+}
+
+/// Allows `requests` requests per `period`, all of which may be used at once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    requests: u32,
+    period: Duration,
+}
+
+impl Budget {
+    /// Parses `<requests>/<seconds>`, or `off` for no limit
+    pub fn parse(budget: &str) -> anyhow::Result<Option<Self>> {
+        let budget = budget.trim();
+        if budget == "off" {
+            return Ok(None);
+        }
+        let (requests, seconds) = budget
+            .split_once('/')
+            .context("expected <requests>/<seconds> or off")?;
+        let requests = requests
+            .trim()
+            .parse::<u32>()
+            .context("invalid number of requests")?;
+        let seconds = seconds
+            .trim()
+            .parse::<u64>()
+            .context("invalid number of seconds")?;
+        if requests == 0 || seconds == 0 {
+            anyhow::bail!("requests and seconds must be greater than 0");
+        }
+        Ok(Some(Budget {
+            requests,
+            period: Duration::from_secs(seconds),
+        }))
+    }
+
+    /// Time it takes to earn back one request
+    fn refill(&self) -> Duration {
+        self.period / self.requests
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub html: Option<Budget>,
+    pub api: Option<Budget>,
+    pub search: Option<Budget>,
+    pub media: Option<Budget>,
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+impl RateLimitConfig {
+    fn budget(&self, class: Class) -> Option<Budget> {
+        match class {
+            Class::Html => self.html,
+            Class::Api => self.api,
+            Class::Search => self.search,
+            Class::Media => self.media,
+        }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    }
+
+    /// The address of the client, looking through trusted proxies. Walks `X-Forwarded-For`
+    /// from the right, as only the entries added by trusted proxies can be relied on.
+    ///
+    /// Peers without an address are on the Unix socket, which only a local proxy can reach.
+    fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        if let Some(peer) = peer.filter(|&peer| !self.is_trusted(peer)) {
+            return Some(peer);
+        }
+
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|ip| ip.trim().parse::<IpAddr>())
+            .collect::<Vec<_>>();
+        let mut client = peer;
+        for ip in forwarded.into_iter().rev() {
+            match ip {
+                Ok(ip) => {
+                    client = Some(ip);
+                    if !self.is_trusted(ip) {
+                        break;
+                    }
+                }
+                // whatever comes before garbage can't be trusted either
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+/// An IP address with a prefix length, like `10.0.0.0/8`. A plain address matches only
+/// itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn parse(network: &str) -> anyhow::Result<Self> {
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (network, None),
+        };
+        let addr = addr.parse::<IpAddr>().context("invalid address")?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().context("invalid prefix length")?,
+            None => max,
+        };
+        if prefix > max {
+            anyhow::bail!("prefix length is longer than the address");
+        }
+        Ok(IpNetwork { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_eq(&network.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_eq(&network.octets(), &ip.octets(), self.prefix)
+            }
+            // proxies on the same host often connect through mapped addresses
+            (IpAddr::V4(_), IpAddr::V6(ip)) => ip
+                .to_ipv4_mapped()
+                .map_or(false, |ip| self.contains(IpAddr::V4(ip))),
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn prefix_eq(a: &[u8], b: &[u8], prefix: u8) -> bool {
+    let bytes = usize::from(prefix / 8);
+    let bits = prefix % 8;
+    if a[..bytes] != b[..bytes] {
+        return false;
+    }
+    bits == 0 || (a[bytes] ^ b[bytes]) >> (8 - bits) == 0
+}
+
+/// Clients on IPv6 usually get a whole /64, so they share one bucket
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or_else(
+            || {
+                let network = u128::from(ip) & !u128::from(u64::MAX);
+                IpAddr::V6(Ipv6Addr::from(network))
+            },
+            IpAddr::V4,
+        ),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// A bucket is full again at `full_at`, and each request pushes that further into the
+/// future. Requests that would push it more than the budget's period ahead are refused.
+#[derive(Debug)]
+struct Bucket {
+    full_at: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<(IpAddr, Class), Bucket>,
+    pruned_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config: Arc::new(config),
+            buckets: Arc::new(Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                pruned_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Takes a request from the client's budget. Returns how long to wait if the budget is
+    /// used up.
+    fn check(&self, ip: IpAddr, class: Class, now: Instant) -> Result<(), Duration> {
+        let budget = match self.config.budget(class) {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if now.saturating_duration_since(buckets.pruned_at) >= PRUNE_INTERVAL {
+            buckets.buckets.retain(|_, bucket| bucket.full_at > now);
+            buckets.pruned_at = now;
+        }
+
+        let bucket = buckets
+            .buckets
+            .entry((client_key(ip), class))
+            .or_insert(Bucket { full_at: now });
+        let full_at = bucket.full_at.max(now) + budget.refill();
+        let ahead = full_at - now;
+        if ahead > budget.period {
+            return Err(ahead.saturating_sub(budget.period));
+        }
+        bucket.full_at = full_at;
+        Ok(())
+    }
+}
+
+/// Middleware answering `429 Too Many Requests` once a client has used up its budget
+pub async fn rate_limit(req: Request<Body>, next: Next<Body>) -> Response {
+    let limiter = req.extensions().get::<RateLimiter>();
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<PeerAddr>>()
+        .and_then(|ConnectInfo(PeerAddr(addr))| *addr);
+    let ip = limiter.and_then(|limiter| limiter.config.client_ip(peer, req.headers()));
+    if let (Some(limiter), Some(ip)) = (limiter, ip) {
+        if let Err(wait) = limiter.check(ip, Class::of(req.uri().path()), Instant::now()) {
+            // round up, retrying early would only be refused again
+            let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many requests",
+            )
+                .into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+#[test]
+fn test_rate_limit() {
+    let config = RateLimitConfig {
+        html: Budget::parse("2/10").unwrap(),
+        api: None,
+        search: None,
+        media: None,
+        trusted_proxies: vec![
+            IpNetwork::parse("10.0.0.0/8").unwrap(),
+            IpNetwork::parse("::1").unwrap(),
+        ],
+    };
+    assert!(Budget::parse("0/10").is_err());
+    assert_eq!(Class::of("/search"), Class::Search);
+    assert_eq!(Class::of("/api/g/post/1/replies"), Class::Api);
+    assert_eq!(Class::of("/g/thread/1"), Class::Html);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        "1.1.1.1, 2.2.2.2, 10.0.0.2".parse().unwrap(),
+    );
+    let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+    assert_eq!(
+        config.client_ip(Some(ip("10.0.0.1")), &headers),
+        Some(ip("2.2.2.2"))
+    );
+    assert_eq!(
+        config.client_ip(Some(ip("::ffff:10.0.0.1")), &headers),
+        Some(ip("2.2.2.2"))
+    );
+    assert_eq!(
+        config.client_ip(Some(ip("3.3.3.3")), &headers),
+        Some(ip("3.3.3.3"))
+    );
+    assert_eq!(config.client_ip(None, &headers), Some(ip("2.2.2.2")));
+    assert_eq!(config.client_ip(None, &HeaderMap::new()), None);
+
+    let limiter = RateLimiter::new(config);
+    let now = Instant::now();
+    assert!(limiter.check(ip("2.2.2.2"), Class::Html, now).is_ok());
+    assert!(limiter.check(ip("2.2.2.2"), Class::Html, now).is_ok());
+    assert_eq!(
+        limiter.check(ip("2.2.2.2"), Class::Html, now),
+        Err(Duration::from_secs(5))
+    );
+    assert!(limiter.check(ip("2.2.2.3"), Class::Html, now).is_ok());
+    assert!(limiter.check(ip("2.2.2.2"), Class::Api, now).is_ok());
+    assert!(limiter
+        .check(ip("2.2.2.2"), Class::Html, now + Duration::from_secs(5))
+        .is_ok());
+}
@@ -1,4 +1,20 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Write};
+
+use fourchan::comment::{Comment, Node, QuoteLink};
+use html_escape::encode_safe;
+use http::StatusCode;
+
+use crate::error::AppError;
+
+/// The page number and row offset for the `page` parameter of a listing. Pages start at 1,
+/// pages too far out to compute an offset for are a bad request.
+pub fn page_offset(page: Option<i64>, per_page: i64) -> Result<(i64, i64), AppError> {
+    let page = page.unwrap_or(1).max(1);
+    (page - 1)
+        .checked_mul(per_page)
+        .map(|offset| (page, offset))
+        .ok_or(AppError::Status(StatusCode::BAD_REQUEST))
+}
 
 pub fn html_decode(
     value: &tera::Value,
@@ -12,3 +28,117 @@ pub fn html_decode(
         _ => Err(tera::Error::msg("found invalid type. expected html string")),
     }
 }
+
+/// Renders a raw 4chan comment as sanitized HTML.
+///
+/// Takes the `board` the post is on and optionally the `thread` it's in. Quotes within the
+/// thread link to the anchor on the current page if `thread` is missing.
+pub fn render_comment(
+    value: &tera::Value,
+    fields: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let board = fields
+        .get("board")
+        .and_then(tera::Value::as_str)
+        .ok_or_else(|| tera::Error::msg("render_comment requires a board"))?;
+    let thread = fields.get("thread").and_then(tera::Value::as_i64);
+
+    match value {
+        tera::Value::Null => Ok(tera::Value::Null),
+        tera::Value::String(html) => Ok(tera::Value::String(comment_html(html, board, thread))),
+        _ => Err(tera::Error::msg("found invalid type. expected html string")),
+    }
+}
+
+/// Renders a raw 4chan comment as sanitized HTML, see [`render_comment`]
+pub fn comment_html(com: &str, board: &str, thread: Option<i64>) -> String {
+    let mut rendered = String::new();
+    render_nodes(&mut rendered, &Comment::parse(com).nodes, board, thread);
+    rendered
+}
+
+fn render_nodes(out: &mut String, nodes: &[Node], board: &str, thread: Option<i64>) {
+    let wrap = |out: &mut String, tag: &str, class: &str, children: &[Node]| {
+        let _ = write!(out, "<{} class=\"{}\">", tag, class);
+        render_nodes(out, children, board, thread);
+        let _ = write!(out, "</{}>", tag);
+    };
+
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(&encode_safe(text)),
+            Node::LineBreak => out.push_str("<br>"),
+            Node::Greentext(children) => wrap(out, "span", "greentext", children),
+            Node::Spoiler(children) => wrap(out, "s", "spoiler", children),
+            Node::Bold(children) => wrap(out, "b", "bold", children),
+            Node::Italic(children) => wrap(out, "i", "italic", children),
+            Node::Underline(children) => wrap(out, "u", "underline", children),
+            Node::Code(code) => {
+                let _ = write!(out, "<pre class=\"code\">{}</pre>", encode_safe(code));
+            }
+            Node::BoardLink(link_board) => {
+                let _ = write!(
+                    out,
+                    "<a class=\"quotelink\" href=\"/{0}\">&gt;&gt;&gt;/{0}/</a>",
+                    encode_safe(link_board)
+                );
+            }
+            Node::Quote(quote) => render_quote(out, quote, board, thread),
+        }
+    }
+}
+
+fn render_quote(out: &mut String, quote: &QuoteLink, board: &str, thread: Option<i64>) {
+    let text = match &quote.board {
+        Some(quote_board) => format!("&gt;&gt;&gt;/{}/{}", encode_safe(quote_board), quote.post),
+        None => format!("&gt;&gt;{}", quote.post),
+    };
+    if quote.dead {
+        let _ = write!(out, "<span class=\"deadlink\">{}</span>", text);
+        return;
+    }
+
+    let href = match (quote.board.as_deref(), quote.thread) {
+        (None, None) => match thread {
+            Some(thread) => format!("/{}/thread/{}#p{}", encode_safe(board), thread, quote.post),
+            None => format!("#p{}", quote.post),
+        },
+        (quote_board, Some(quote_thread)) => format!(
+            "/{}/thread/{}#p{}",
+            encode_safe(quote_board.unwrap_or(board)),
+            quote_thread,
+            quote.post
+        ),
+        (Some(quote_board), None) => format!("/{}", encode_safe(quote_board)),
+    };
+    let _ = write!(
+        out,
+        "<a class=\"quotelink\" href=\"{}\" data-post=\"{}\">{}</a>",
+        href, quote.post, text
+    );
+}
+
+#[test]
+fn test_page_offset() {
+    assert_eq!(page_offset(None, 50).unwrap(), (1, 0));
+    assert_eq!(page_offset(Some(-3), 50).unwrap(), (1, 0));
+    assert_eq!(page_offset(Some(3), 50).unwrap(), (3, 100));
+    assert!(page_offset(Some(i64::MAX), 50).is_err());
+}
+
+#[test]
+fn test_render_comment_escapes() {
+    let mut fields = HashMap::new();
+    fields.insert("board".to_string(), tera::Value::from("g"));
+    let rendered = render_comment(
+        &tera::Value::from(
+            r##"<a href="#p1" class="quotelink">&gt;&gt;1</a><br><script>alert("x")</script><img src=x onerror=alert(1)>&lt;b&gt;"##,
+        ),
+        &fields,
+    )
+    .unwrap();
+    assert_eq!(
+        rendered.as_str().unwrap(),
+        r##"<a class="quotelink" href="#p1" data-post="1">&gt;&gt;1</a><br>alert(&quot;x&quot;)&lt;b&gt;"##
+    );
+}